@@ -1,5 +1,14 @@
+// Follows the vertex shader's transform in the push constants.
+layout(push_constant) uniform ColorAdjustment {
+  layout(offset = 64) float exposure;
+  float gamma;
+} adjustment;
+
+layout(location = 0) in vec3 vertex_color;
+
 layout(location = 0) out vec4 color;
 
 void main() {
-  color = vec4(0.5, 0.5, 1.0, 1.0);
+  vec3 base = vertex_color * adjustment.exposure;
+  color = vec4(pow(base, vec3(1.0 / adjustment.gamma)), 1.0);
 }
@@ -0,0 +1,48 @@
+// The fast variant of Timothy Lottes' FXAA: edges are found from the luma contrast between the
+// corners of a pixel and blurred along their direction, pixels without edges are left as is.
+const float FXAA_REDUCE_MIN = 1.0 / 128.0;
+const float FXAA_REDUCE_MUL = 1.0 / 8.0;
+// Furthest an edge is followed, in pixels.
+const float FXAA_SPAN_MAX = 8.0;
+
+float fxaa_luma(vec3 rgb) {
+  // The sampled colors are linear, but contrast is judged perceptually.
+  return sqrt(dot(rgb, vec3(0.299, 0.587, 0.114)));
+}
+
+vec3 fxaa(sampler2D image, vec2 uv, vec2 texel_size) {
+  float luma_nw = fxaa_luma(texture(image, uv + vec2(-0.5, -0.5) * texel_size).rgb);
+  float luma_ne = fxaa_luma(texture(image, uv + vec2(0.5, -0.5) * texel_size).rgb);
+  float luma_sw = fxaa_luma(texture(image, uv + vec2(-0.5, 0.5) * texel_size).rgb);
+  float luma_se = fxaa_luma(texture(image, uv + vec2(0.5, 0.5) * texel_size).rgb);
+  float luma_m = fxaa_luma(texture(image, uv).rgb);
+
+  float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+  float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+  // Perpendicular to the luma gradient, so along the edge.
+  vec2 direction = vec2(
+    (luma_sw + luma_se) - (luma_nw + luma_ne),
+    (luma_nw + luma_sw) - (luma_ne + luma_se)
+  );
+
+  float direction_reduce = max(
+    (luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * FXAA_REDUCE_MUL,
+    FXAA_REDUCE_MIN
+  );
+  float scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + direction_reduce);
+  direction = clamp(direction * scale, -FXAA_SPAN_MAX, FXAA_SPAN_MAX) * texel_size;
+
+  vec3 rgb_a = 0.5 * (
+    texture(image, uv + direction * (1.0 / 3.0 - 0.5)).rgb +
+    texture(image, uv + direction * (2.0 / 3.0 - 0.5)).rgb
+  );
+  vec3 rgb_b = rgb_a * 0.5 + 0.25 * (
+    texture(image, uv - direction * 0.5).rgb +
+    texture(image, uv + direction * 0.5).rgb
+  );
+
+  // The wider blur crossed into another edge when its luma leaves the neighbourhood's range.
+  float luma_b = fxaa_luma(rgb_b);
+  return luma_b < luma_min || luma_b > luma_max ? rgb_a : rgb_b;
+}
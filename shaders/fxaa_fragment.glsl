@@ -0,0 +1,15 @@
+#include "fxaa.glsl"
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+
+layout(push_constant) uniform Fxaa {
+  vec2 texel_size;
+} fxaa_constants;
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 color;
+
+void main() {
+  color = vec4(fxaa(scene, uv, fxaa_constants.texel_size), texture(scene, uv).a);
+}
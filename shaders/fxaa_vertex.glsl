@@ -0,0 +1,8 @@
+// Single triangle covering the screen, drawn without vertex buffers.
+layout(location = 0) out vec2 uv;
+
+void main() {
+  // 0 to 1 across the visible part of the triangle.
+  uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+  gl_Position = vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,22 @@
+// Follows the vertex shader's transform in the push constants.
+layout(push_constant) uniform Grid {
+  layout(offset = 64) vec4 color;
+  float spacing;
+} grid;
+
+layout(location = 0) in vec2 world_position;
+
+layout(location = 0) out vec4 color;
+
+void main() {
+  // Distance to the closest line in pixels, which keeps the lines a pixel wide at any distance.
+  vec2 cell = world_position / grid.spacing;
+  vec2 line_distance = abs(fract(cell - 0.5) - 0.5) / fwidth(cell);
+  float coverage = 1.0 - min(min(line_distance.x, line_distance.y), 1.0);
+
+  if (coverage <= 0.0) {
+    discard;
+  }
+
+  color = vec4(grid.color.rgb, grid.color.a * coverage);
+}
@@ -0,0 +1,19 @@
+// Square on the y = 0 plane centered on the origin, the grid lines are computed per fragment.
+layout(push_constant) uniform Grid {
+  mat4 model_view_projection;
+  vec4 color;
+  float spacing;
+  float extent;
+} grid;
+
+layout(location = 0) out vec2 world_position;
+
+const vec2 CORNERS[6] = vec2[](
+  vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+  vec2(-1.0, -1.0), vec2(1.0, 1.0), vec2(-1.0, 1.0)
+);
+
+void main() {
+  world_position = CORNERS[gl_VertexIndex] * grid.extent;
+  gl_Position = grid.model_view_projection * vec4(world_position.x, 0.0, world_position.y, 1.0);
+}
@@ -0,0 +1,9 @@
+layout(set = 0, binding = 0) uniform sampler2D texture_sampler;
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 color;
+
+void main() {
+  color = texture(texture_sampler, uv);
+}
@@ -0,0 +1,14 @@
+layout(push_constant) uniform Transform {
+  mat4 model_view_projection;
+} transform;
+
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec3 color;
+
+layout(location = 0) out vec2 uv;
+
+void main() {
+  // The quad spans -0.5 to 0.5, so the texture coordinates follow from its positions.
+  uv = position + 0.5;
+  gl_Position = transform.model_view_projection * vec4(position, 0.0, 1.0);
+}
@@ -1,10 +1,13 @@
-vec2 positions[3] = vec2[](
-  vec2(0.0, -0.5),
-  vec2(-0.5, 0.5),
-  vec2(0.5, -0.5)
-);
+layout(push_constant) uniform Transform {
+  mat4 model_view_projection;
+} transform;
+
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec3 color;
+
+layout(location = 0) out vec3 vertex_color;
 
 void main() {
-  vec2 position = positions[gl_VertexIndex];
-  gl_Position = vec4(position, 0.0, 1.0);
+  vertex_color = color;
+  gl_Position = transform.model_view_projection * vec4(position, 0.0, 1.0);
 }
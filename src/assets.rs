@@ -0,0 +1,157 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Environment variable naming an extra directory to search for assets.
+pub const ASSET_DIR_VAR: &str = "ASSET_DIR";
+
+/// Resolves relative asset names such as `shaders/vertex.glsl` against a list of root
+/// directories, so the binary doesn't depend on being run from the repository root.
+#[derive(Debug, Clone)]
+pub struct AssetResolver {
+    roots: Vec<PathBuf>,
+}
+
+impl AssetResolver {
+    pub fn new(roots: Vec<PathBuf>) -> Self {
+        Self { roots }
+    }
+
+    /// Searches `$ASSET_DIR`, the current working directory and then the executable's directory.
+    pub fn from_env() -> Self {
+        let mut roots = Vec::new();
+
+        if let Some(dir) = std::env::var_os(ASSET_DIR_VAR) {
+            roots.push(PathBuf::from(dir));
+        }
+
+        if let Ok(dir) = std::env::current_dir() {
+            roots.push(dir);
+        }
+
+        if let Some(dir) = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        {
+            roots.push(dir);
+        }
+
+        Self::new(roots)
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Adds a root that is searched after all the existing ones.
+    pub fn push_root(&mut self, root: impl Into<PathBuf>) {
+        self.roots.push(root.into());
+    }
+
+    /// Absolute path of the first root containing `name`.
+    pub fn resolve(&self, name: impl AsRef<Path>) -> Result<PathBuf, AssetNotFound> {
+        let name = name.as_ref();
+
+        if name.is_absolute() {
+            return if name.exists() {
+                Ok(name.to_path_buf())
+            } else {
+                Err(AssetNotFound {
+                    name: name.to_path_buf(),
+                    searched: vec![name.to_path_buf()],
+                })
+            };
+        }
+
+        let searched: Vec<PathBuf> = self.roots.iter().map(|root| root.join(name)).collect();
+
+        match searched.iter().find(|path| path.exists()) {
+            Some(path) => Ok(path.clone()),
+            None => Err(AssetNotFound {
+                name: name.to_path_buf(),
+                searched,
+            }),
+        }
+    }
+
+    pub fn read(&self, name: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+        std::fs::read(self.resolve(name)?)
+    }
+}
+
+impl Default for AssetResolver {
+    fn default() -> Self {
+        Self::from_env()
+    }
+}
+
+/// Returned when an asset doesn't exist in any of the searched locations.
+#[derive(Debug, Clone)]
+pub struct AssetNotFound {
+    pub name: PathBuf,
+    pub searched: Vec<PathBuf>,
+}
+
+impl fmt::Display for AssetNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Asset {} not found, searched:", self.name.display())?;
+
+        for path in &self.searched {
+            write!(f, "\n    {}", path.display())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for AssetNotFound {}
+
+impl From<AssetNotFound> for io::Error {
+    fn from(err: AssetNotFound) -> Self {
+        io::Error::new(io::ErrorKind::NotFound, err)
+    }
+}
+
+/// Detects changes to a set of files by polling their modification times.
+#[derive(Debug, Clone)]
+pub struct FileWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl FileWatcher {
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        Self {
+            files: paths
+                .into_iter()
+                .map(|path| {
+                    let modified = modified(&path);
+                    (path, modified)
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether any of the files was modified since the last call. Files that can't be accessed,
+    /// e.g. whilst an editor is replacing them, are ignored until they reappear.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+
+        for (path, last_modified) in &mut self.files {
+            let modified = modified(path);
+
+            if modified.is_some() && modified != *last_modified {
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
@@ -0,0 +1,156 @@
+use gfx_hal::adapter::MemoryType;
+use gfx_hal::device::Device;
+use gfx_hal::format::{Aspects, Format, Swizzle};
+use gfx_hal::image::{
+    Kind, NumSamples, SubresourceRange, Tiling, Usage, ViewCapabilities, ViewKind,
+};
+use gfx_hal::memory::Properties;
+use gfx_hal::window::Extent2D;
+
+use crate::buffer::find_memory_type;
+use crate::RenderError;
+
+/// Format of the depth attachment, which every desktop GPU supports.
+pub const DEPTH_FORMAT: Format = Format::D32Sfloat;
+
+/// An image with its own memory, rendered to as a framebuffer attachment.
+pub struct AttachmentImage<B: gfx_hal::Backend> {
+    pub image: B::Image,
+    pub memory: B::Memory,
+    pub view: B::ImageView,
+}
+
+impl<B: gfx_hal::Backend> AttachmentImage<B> {
+    /// # Safety
+    ///
+    /// `memory_types` must have been queried from the physical device `device` was opened on.
+    pub unsafe fn new(
+        device: &B::Device,
+        memory_types: &[MemoryType],
+        extent: Extent2D,
+        format: Format,
+        usage: Usage,
+        samples: NumSamples,
+    ) -> Result<Self, RenderError> {
+        let mut image = device.create_image(
+            Kind::D2(extent.width, extent.height, 1, samples),
+            1,
+            format,
+            Tiling::Optimal,
+            usage,
+            ViewCapabilities::empty(),
+        )?;
+        let requirements = device.get_image_requirements(&image);
+
+        let memory = find_memory_type(
+            memory_types,
+            requirements.type_mask,
+            Properties::DEVICE_LOCAL,
+        )
+        .ok_or(RenderError::NoMemoryType(Properties::DEVICE_LOCAL))
+        .and_then(|memory_type| Ok(device.allocate_memory(memory_type, requirements.size)?));
+
+        let memory = match memory {
+            Ok(memory) => memory,
+            Err(error) => {
+                device.destroy_image(image);
+                return Err(error);
+            }
+        };
+
+        if let Err(error) = device.bind_image_memory(&memory, 0, &mut image) {
+            device.destroy_image(image);
+            device.free_memory(memory);
+            return Err(error.into());
+        }
+
+        let view = device.create_image_view(
+            &image,
+            ViewKind::D2,
+            format,
+            Swizzle::NO,
+            SubresourceRange {
+                aspects: format.surface_desc().aspects,
+                level_start: 0,
+                level_count: Some(1),
+                layer_start: 0,
+                layer_count: Some(1),
+            },
+        );
+
+        match view {
+            Ok(view) => Ok(Self {
+                image,
+                memory,
+                view,
+            }),
+            Err(error) => {
+                device.destroy_image(image);
+                device.free_memory(memory);
+                Err(error.into())
+            }
+        }
+    }
+
+    /// Depth attachment matching the size and sample count of the color attachments.
+    ///
+    /// # Safety
+    ///
+    /// Same requirements as [`AttachmentImage::new`].
+    pub unsafe fn depth(
+        device: &B::Device,
+        memory_types: &[MemoryType],
+        extent: Extent2D,
+        samples: NumSamples,
+    ) -> Result<Self, RenderError> {
+        Self::new(
+            device,
+            memory_types,
+            extent,
+            DEPTH_FORMAT,
+            Usage::DEPTH_STENCIL_ATTACHMENT,
+            samples,
+        )
+    }
+
+    /// Multisampled color attachment, which is only rendered to and resolved into a single
+    /// sampled image at the end of the render pass.
+    ///
+    /// # Safety
+    ///
+    /// Same requirements as [`AttachmentImage::new`].
+    pub unsafe fn multisampled(
+        device: &B::Device,
+        memory_types: &[MemoryType],
+        extent: Extent2D,
+        format: Format,
+        samples: NumSamples,
+    ) -> Result<Self, RenderError> {
+        Self::new(
+            device,
+            memory_types,
+            extent,
+            format,
+            Usage::COLOR_ATTACHMENT | Usage::TRANSIENT_ATTACHMENT,
+            samples,
+        )
+    }
+
+    /// # Safety
+    ///
+    /// The image must have been created by `device` and no longer be in use by the GPU.
+    pub unsafe fn destroy(self, device: &B::Device) {
+        device.destroy_image_view(self.view);
+        device.destroy_image(self.image);
+        device.free_memory(self.memory);
+    }
+}
+
+/// Subresource covering the color aspect of an attachment.
+pub const COLOR_RANGE: SubresourceRange = SubresourceRange {
+    aspects: Aspects::COLOR,
+    level_start: 0,
+    level_count: Some(1),
+    layer_start: 0,
+    layer_count: Some(1),
+};
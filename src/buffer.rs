@@ -0,0 +1,409 @@
+use gfx_hal::adapter::MemoryType;
+use gfx_hal::device::Device;
+use gfx_hal::memory::{Properties, Segment};
+use gfx_hal::{IndexType, MemoryTypeId};
+
+use crate::vertex::VertexFormat;
+use crate::RenderError;
+
+/// First of the `memory_types` allowed by `type_mask` that has all the given `properties`.
+pub fn find_memory_type(
+    memory_types: &[MemoryType],
+    type_mask: u32,
+    properties: Properties,
+) -> Option<MemoryTypeId> {
+    memory_types
+        .iter()
+        .enumerate()
+        .find(|(id, memory_type)| {
+            type_mask & (1 << id) != 0 && memory_type.properties.contains(properties)
+        })
+        .map(|(id, _)| MemoryTypeId(id))
+}
+
+/// A buffer together with the memory bound to it.
+pub struct Buffer<B: gfx_hal::Backend> {
+    pub buffer: B::Buffer,
+    pub memory: B::Memory,
+    /// Size of the contents in bytes, the allocation itself may be larger.
+    pub size: u64,
+}
+
+impl<B: gfx_hal::Backend> Buffer<B> {
+    /// Creates an uninitialized CPU visible buffer of `size` bytes.
+    ///
+    /// # Safety
+    ///
+    /// `memory_types` must have been queried from the physical device `device` was opened on.
+    pub unsafe fn new(
+        device: &B::Device,
+        memory_types: &[MemoryType],
+        usage: gfx_hal::buffer::Usage,
+        size: u64,
+    ) -> Result<Self, RenderError> {
+        let mut buffer = device.create_buffer(size, usage)?;
+        let requirements = device.get_buffer_requirements(&buffer);
+
+        let memory = find_memory_type(
+            memory_types,
+            requirements.type_mask,
+            Properties::CPU_VISIBLE,
+        )
+        .ok_or(RenderError::NoMemoryType(Properties::CPU_VISIBLE))
+        .and_then(|memory_type| Ok(device.allocate_memory(memory_type, requirements.size)?));
+
+        let memory = match memory {
+            Ok(memory) => memory,
+            Err(error) => {
+                device.destroy_buffer(buffer);
+                return Err(error);
+            }
+        };
+
+        if let Err(error) = device.bind_buffer_memory(&memory, 0, &mut buffer) {
+            device.destroy_buffer(buffer);
+            device.free_memory(memory);
+            return Err(error.into());
+        }
+
+        Ok(Self {
+            buffer,
+            memory,
+            size,
+        })
+    }
+
+    /// Creates a CPU visible buffer with the given `usage` and copies `data` into it.
+    ///
+    /// Keeping the buffer in CPU visible memory avoids a staging copy, which is fine for small
+    /// meshes that are uploaded once.
+    ///
+    /// # Safety
+    ///
+    /// Same requirements as [`Buffer::new`].
+    pub unsafe fn upload<T: Copy>(
+        device: &B::Device,
+        memory_types: &[MemoryType],
+        usage: gfx_hal::buffer::Usage,
+        data: &[T],
+    ) -> Result<Self, RenderError> {
+        let buffer = Self::new(
+            device,
+            memory_types,
+            usage,
+            std::mem::size_of_val(data) as u64,
+        )?;
+
+        match buffer.write(device, data) {
+            Ok(()) => Ok(buffer),
+            Err(error) => {
+                buffer.destroy(device);
+                Err(error)
+            }
+        }
+    }
+
+    // Copies `data` to the start of the buffer, which must be large enough to hold it.
+    unsafe fn write<T: Copy>(&self, device: &B::Device, data: &[T]) -> Result<(), RenderError> {
+        let mapping = device.map_memory(&self.memory, Segment::ALL)?;
+        std::ptr::copy_nonoverlapping(
+            data.as_ptr() as *const u8,
+            mapping,
+            std::mem::size_of_val(data),
+        );
+
+        let flushed =
+            device.flush_mapped_memory_ranges(std::iter::once((&self.memory, Segment::ALL)));
+        device.unmap_memory(&self.memory);
+
+        Ok(flushed?)
+    }
+
+    /// Copies the contents back from the GPU.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must have been created by `device` and any writes to it must have completed.
+    pub unsafe fn read(&self, device: &B::Device) -> Result<Vec<u8>, RenderError> {
+        let mapping = device.map_memory(&self.memory, Segment::ALL)?;
+
+        let data = device
+            .invalidate_mapped_memory_ranges(std::iter::once((&self.memory, Segment::ALL)))
+            .map(|_| std::slice::from_raw_parts(mapping, self.size as usize).to_vec());
+        device.unmap_memory(&self.memory);
+
+        Ok(data?)
+    }
+
+    /// # Safety
+    ///
+    /// The buffer must have been created by `device` and no longer be in use by the GPU.
+    pub unsafe fn destroy(self, device: &B::Device) {
+        device.destroy_buffer(self.buffer);
+        device.free_memory(self.memory);
+    }
+}
+
+/// Integer types that can be stored in an index buffer.
+pub trait Index: Copy {
+    const TYPE: IndexType;
+}
+
+impl Index for u16 {
+    const TYPE: IndexType = IndexType::U16;
+}
+
+impl Index for u32 {
+    const TYPE: IndexType = IndexType::U32;
+}
+
+/// Indices into the vertices of a mesh, along with how many of them to draw.
+pub struct IndexBuffer<B: gfx_hal::Backend> {
+    pub buffer: Buffer<B>,
+    pub index_type: IndexType,
+    pub index_count: u32,
+}
+
+impl<B: gfx_hal::Backend> IndexBuffer<B> {
+    /// # Safety
+    ///
+    /// Same requirements as [`Buffer::upload`].
+    pub unsafe fn upload<I: Index>(
+        device: &B::Device,
+        memory_types: &[MemoryType],
+        indices: &[I],
+    ) -> Result<Self, RenderError> {
+        Ok(Self {
+            buffer: Buffer::upload(device, memory_types, gfx_hal::buffer::Usage::INDEX, indices)?,
+            index_type: I::TYPE,
+            index_count: indices.len() as u32,
+        })
+    }
+
+    /// Range of indices to pass to `draw_indexed`.
+    pub fn indices(&self) -> std::ops::Range<u32> {
+        0..self.index_count
+    }
+}
+
+/// Vertices uploaded to the GPU, along with how many of them to draw.
+pub struct Mesh<B: gfx_hal::Backend> {
+    pub vertex_buffer: Buffer<B>,
+    pub vertex_count: u32,
+    /// Meshes with indices are drawn through them instead of taking the vertices in order.
+    pub index_buffer: Option<IndexBuffer<B>>,
+}
+
+impl<B: gfx_hal::Backend> Mesh<B> {
+    /// # Safety
+    ///
+    /// Same requirements as [`Buffer::upload`].
+    pub unsafe fn upload<V: VertexFormat>(
+        device: &B::Device,
+        memory_types: &[MemoryType],
+        vertices: &[V],
+    ) -> Result<Self, RenderError> {
+        Ok(Self {
+            vertex_buffer: Buffer::upload(
+                device,
+                memory_types,
+                gfx_hal::buffer::Usage::VERTEX,
+                vertices,
+            )?,
+            vertex_count: vertices.len() as u32,
+            index_buffer: None,
+        })
+    }
+
+    /// Uploads a mesh that's drawn through `indices`, letting triangles share vertices.
+    ///
+    /// # Safety
+    ///
+    /// Same requirements as [`Buffer::upload`].
+    pub unsafe fn upload_indexed<V: VertexFormat, I: Index>(
+        device: &B::Device,
+        memory_types: &[MemoryType],
+        vertices: &[V],
+        indices: &[I],
+    ) -> Result<Self, RenderError> {
+        let mesh = Self::upload(device, memory_types, vertices)?;
+
+        match IndexBuffer::upload(device, memory_types, indices) {
+            Ok(index_buffer) => Ok(Self {
+                index_buffer: Some(index_buffer),
+                ..mesh
+            }),
+            Err(error) => {
+                mesh.destroy(device);
+                Err(error)
+            }
+        }
+    }
+
+    /// Range of vertices to pass to `draw`.
+    pub fn vertices(&self) -> std::ops::Range<u32> {
+        0..self.vertex_count
+    }
+
+    /// # Safety
+    ///
+    /// Same requirements as [`Buffer::destroy`].
+    pub unsafe fn destroy(self, device: &B::Device) {
+        self.vertex_buffer.destroy(device);
+        if let Some(index_buffer) = self.index_buffer {
+            index_buffer.buffer.destroy(device);
+        }
+    }
+}
+
+/// Parameters of a single indexed draw read from an indirect buffer by `draw_indexed_indirect`,
+/// laid out like Vulkan's `VkDrawIndexedIndirectCommand`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct DrawIndexedCommand {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    /// Added to each index before reading the vertex.
+    pub vertex_offset: i32,
+    pub first_instance: u32,
+}
+
+impl DrawIndexedCommand {
+    /// Distance in bytes between consecutive commands in an indirect buffer.
+    pub const STRIDE: u32 = std::mem::size_of::<Self>() as u32;
+}
+
+/// Commands drawing each of `meshes` once after they're packed one after another into a single
+/// vertex and index buffer, as [`MeshBatch::upload`] does. Indices stay relative to their own
+/// mesh's vertices.
+pub fn draw_indexed_commands<V, I>(meshes: &[(&[V], &[I])]) -> Vec<DrawIndexedCommand> {
+    let mut first_index = 0;
+    let mut vertex_offset = 0;
+
+    meshes
+        .iter()
+        .map(|(vertices, indices)| {
+            let command = DrawIndexedCommand {
+                index_count: indices.len() as u32,
+                instance_count: 1,
+                first_index,
+                vertex_offset,
+                first_instance: 0,
+            };
+
+            first_index += indices.len() as u32;
+            vertex_offset += vertices.len() as i32;
+            command
+        })
+        .collect()
+}
+
+/// Meshes sharing a vertex and index buffer, drawn by `draw_indexed_indirect` from an indirect
+/// buffer holding a [`DrawIndexedCommand`] per mesh. Since the parameters live in GPU memory they
+/// can also be written by the GPU itself, e.g. by a culling pass.
+pub struct MeshBatch<B: gfx_hal::Backend> {
+    /// All the meshes' vertices and indices.
+    pub mesh: Mesh<B>,
+    pub indirect_buffer: Buffer<B>,
+    /// Number of commands in `indirect_buffer`, one per mesh in the order they were uploaded.
+    pub draw_count: u32,
+}
+
+impl<B: gfx_hal::Backend> MeshBatch<B> {
+    /// # Safety
+    ///
+    /// Same requirements as [`Buffer::upload`].
+    pub unsafe fn upload<V: VertexFormat, I: Index>(
+        device: &B::Device,
+        memory_types: &[MemoryType],
+        meshes: &[(&[V], &[I])],
+    ) -> Result<Self, RenderError> {
+        let vertices: Vec<V> = meshes
+            .iter()
+            .flat_map(|(vertices, _)| vertices.iter().copied())
+            .collect();
+        let indices: Vec<I> = meshes
+            .iter()
+            .flat_map(|(_, indices)| indices.iter().copied())
+            .collect();
+        let commands = draw_indexed_commands(meshes);
+
+        let mesh = Mesh::upload_indexed(device, memory_types, &vertices, &indices)?;
+        let indirect_buffer = Buffer::upload(
+            device,
+            memory_types,
+            gfx_hal::buffer::Usage::INDIRECT,
+            &commands,
+        );
+
+        match indirect_buffer {
+            Ok(indirect_buffer) => Ok(Self {
+                mesh,
+                indirect_buffer,
+                draw_count: commands.len() as u32,
+            }),
+            Err(error) => {
+                mesh.destroy(device);
+                Err(error)
+            }
+        }
+    }
+
+    /// Byte offset of the command drawing the `idx`th mesh within the indirect buffer.
+    pub fn command_offset(&self, idx: usize) -> u64 {
+        idx as u64 * DrawIndexedCommand::STRIDE as u64
+    }
+
+    /// # Safety
+    ///
+    /// Same requirements as [`Buffer::destroy`].
+    pub unsafe fn destroy(self, device: &B::Device) {
+        self.mesh.destroy(device);
+        self.indirect_buffer.destroy(device);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draw_commands_follow_packed_meshes() {
+        let triangle: (&[u8], &[u16]) = (&[0; 3], &[0, 1, 2]);
+        let quad: (&[u8], &[u16]) = (&[0; 4], &[0, 1, 2, 0, 2, 3]);
+
+        assert_eq!(
+            draw_indexed_commands(&[triangle, quad, triangle]),
+            [
+                DrawIndexedCommand {
+                    index_count: 3,
+                    instance_count: 1,
+                    first_index: 0,
+                    vertex_offset: 0,
+                    first_instance: 0,
+                },
+                DrawIndexedCommand {
+                    index_count: 6,
+                    instance_count: 1,
+                    first_index: 3,
+                    vertex_offset: 3,
+                    first_instance: 0,
+                },
+                DrawIndexedCommand {
+                    index_count: 3,
+                    instance_count: 1,
+                    first_index: 9,
+                    vertex_offset: 7,
+                    first_instance: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn draw_command_matches_vulkan_layout() {
+        assert_eq!(DrawIndexedCommand::STRIDE, 20);
+        assert_eq!(std::mem::offset_of!(DrawIndexedCommand, vertex_offset), 12);
+    }
+}
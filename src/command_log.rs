@@ -0,0 +1,423 @@
+use gfx_hal::command::CommandBuffer;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::path::Path;
+
+/// A render command as issued by the render loop. Resources are referred to by their index in
+/// `GpuResources` so the log stays comparable between runs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RenderCommand {
+    BeginRenderPass {
+        render_pass: usize,
+        clear_color: [f32; 4],
+    },
+    BindPipeline {
+        pipeline: usize,
+    },
+    BindVertexBuffers {
+        first_binding: u32,
+        buffers: Vec<usize>,
+    },
+    BindDescriptorSets {
+        pipeline_layout: usize,
+        first_set: usize,
+        sets: Vec<usize>,
+    },
+    BindIndexBuffer {
+        buffer: usize,
+        /// Size of each index in bytes.
+        index_size: u32,
+    },
+    PushConstants {
+        pipeline_layout: usize,
+        offset: u32,
+        constants: Vec<u32>,
+    },
+    Draw {
+        vertices: Range<u32>,
+        instances: Range<u32>,
+    },
+    DrawIndexed {
+        indices: Range<u32>,
+        base_vertex: i32,
+        instances: Range<u32>,
+    },
+    DrawIndexedIndirect {
+        buffer: usize,
+        offset: u64,
+        draw_count: u32,
+        stride: u32,
+    },
+    /// Commands recorded by user code, which aren't logged individually.
+    Custom,
+    EndRenderPass,
+}
+
+/// All the commands recorded for a single frame.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FrameLog {
+    pub commands: Vec<RenderCommand>,
+}
+
+impl FrameLog {
+    pub fn save(&self, path: impl AsRef<Path>) -> bincode::Result<()> {
+        let file = std::fs::File::create(path)?;
+        bincode::serialize_into(std::io::BufWriter::new(file), self)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> bincode::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        bincode::deserialize_from(std::io::BufReader::new(file))
+    }
+
+    /// Index and commands of the first difference between `self` and `expected`. A missing
+    /// command on either side is reported as `None`.
+    pub fn first_mismatch<'a>(
+        &'a self,
+        expected: &'a FrameLog,
+    ) -> Option<(usize, Option<&'a RenderCommand>, Option<&'a RenderCommand>)> {
+        let len = self.commands.len().max(expected.commands.len());
+
+        (0..len)
+            .map(|idx| (idx, self.commands.get(idx), expected.commands.get(idx)))
+            .find(|(_, actual, expected)| actual != expected)
+    }
+}
+
+/// What to do with the commands recorded during the first frame.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum CommandLogMode {
+    #[default]
+    Off,
+    /// Save the frame's commands to the given file.
+    Record(std::path::PathBuf),
+    /// Compare the frame's commands against a previously recorded file, then exit.
+    Verify(std::path::PathBuf),
+}
+
+/// Forwards commands to a command buffer whilst optionally logging them.
+pub struct CommandRecorder<'a, B: gfx_hal::Backend> {
+    command_buffer: &'a mut B::CommandBuffer,
+    log: Option<&'a mut FrameLog>,
+}
+
+impl<'a, B: gfx_hal::Backend> CommandRecorder<'a, B> {
+    pub fn new(command_buffer: &'a mut B::CommandBuffer, log: Option<&'a mut FrameLog>) -> Self {
+        Self {
+            command_buffer,
+            log,
+        }
+    }
+
+    fn record(&mut self, command: impl FnOnce() -> RenderCommand) {
+        if let Some(log) = self.log.as_mut() {
+            log.commands.push(command());
+        }
+    }
+
+    /// Clears the first attachment to `clear_color` and the depth attachment, if any, to 1.0.
+    ///
+    /// # Safety
+    ///
+    /// Same requirements as `CommandBuffer::begin_render_pass`.
+    pub unsafe fn begin_render_pass(
+        &mut self,
+        render_pass: (usize, &B::RenderPass),
+        framebuffer: &B::Framebuffer,
+        render_area: gfx_hal::pso::Rect,
+        clear_color: [f32; 4],
+    ) {
+        use gfx_hal::command::{ClearColor, ClearDepthStencil, ClearValue, SubpassContents};
+
+        self.record(|| RenderCommand::BeginRenderPass {
+            render_pass: render_pass.0,
+            clear_color,
+        });
+
+        self.command_buffer.begin_render_pass(
+            render_pass.1,
+            framebuffer,
+            render_area,
+            [
+                ClearValue {
+                    color: ClearColor {
+                        float32: clear_color,
+                    },
+                },
+                ClearValue {
+                    depth_stencil: ClearDepthStencil {
+                        depth: 1.0,
+                        stencil: 0,
+                    },
+                },
+            ],
+            SubpassContents::Inline,
+        );
+    }
+
+    /// # Safety
+    ///
+    /// Same requirements as `CommandBuffer::bind_graphics_pipeline`.
+    pub unsafe fn bind_graphics_pipeline(&mut self, pipeline: (usize, &B::GraphicsPipeline)) {
+        self.record(|| RenderCommand::BindPipeline {
+            pipeline: pipeline.0,
+        });
+
+        self.command_buffer.bind_graphics_pipeline(pipeline.1);
+    }
+
+    /// Binds each of the whole `buffers` to consecutive bindings starting at `first_binding`.
+    ///
+    /// # Safety
+    ///
+    /// Same requirements as `CommandBuffer::bind_vertex_buffers`.
+    pub unsafe fn bind_vertex_buffers(
+        &mut self,
+        first_binding: u32,
+        buffers: &[(usize, &B::Buffer)],
+    ) {
+        use gfx_hal::buffer::SubRange;
+
+        self.record(|| RenderCommand::BindVertexBuffers {
+            first_binding,
+            buffers: buffers.iter().map(|(idx, _)| *idx).collect(),
+        });
+
+        self.command_buffer.bind_vertex_buffers(
+            first_binding,
+            buffers.iter().map(|(_, buffer)| (*buffer, SubRange::WHOLE)),
+        );
+    }
+
+    /// # Safety
+    ///
+    /// Same requirements as `CommandBuffer::bind_graphics_descriptor_sets`.
+    pub unsafe fn bind_graphics_descriptor_sets(
+        &mut self,
+        pipeline_layout: (usize, &B::PipelineLayout),
+        first_set: usize,
+        sets: &[(usize, &B::DescriptorSet)],
+    ) {
+        self.record(|| RenderCommand::BindDescriptorSets {
+            pipeline_layout: pipeline_layout.0,
+            first_set,
+            sets: sets.iter().map(|(idx, _)| *idx).collect(),
+        });
+
+        self.command_buffer.bind_graphics_descriptor_sets(
+            pipeline_layout.1,
+            first_set,
+            sets.iter().map(|(_, set)| *set),
+            &[],
+        );
+    }
+
+    /// Binds the whole `buffer` as the index buffer for the following indexed draws.
+    ///
+    /// # Safety
+    ///
+    /// Same requirements as `CommandBuffer::bind_index_buffer`.
+    pub unsafe fn bind_index_buffer(
+        &mut self,
+        buffer: (usize, &B::Buffer),
+        index_type: gfx_hal::IndexType,
+    ) {
+        use gfx_hal::buffer::{IndexBufferView, SubRange};
+
+        self.record(|| RenderCommand::BindIndexBuffer {
+            buffer: buffer.0,
+            index_size: match index_type {
+                gfx_hal::IndexType::U16 => 2,
+                gfx_hal::IndexType::U32 => 4,
+            },
+        });
+
+        self.command_buffer.bind_index_buffer(IndexBufferView {
+            buffer: buffer.1,
+            range: SubRange::WHOLE,
+            index_type,
+        });
+    }
+
+    /// # Safety
+    ///
+    /// Same requirements as `CommandBuffer::push_graphics_constants`.
+    pub unsafe fn push_graphics_constants(
+        &mut self,
+        pipeline_layout: (usize, &B::PipelineLayout),
+        stages: gfx_hal::pso::ShaderStageFlags,
+        offset: u32,
+        constants: &[u32],
+    ) {
+        self.record(|| RenderCommand::PushConstants {
+            pipeline_layout: pipeline_layout.0,
+            offset,
+            constants: constants.to_vec(),
+        });
+
+        self.command_buffer
+            .push_graphics_constants(pipeline_layout.1, stages, offset, constants);
+    }
+
+    /// # Safety
+    ///
+    /// Same requirements as `CommandBuffer::draw`.
+    pub unsafe fn draw(&mut self, vertices: Range<u32>, instances: Range<u32>) {
+        self.record(|| RenderCommand::Draw {
+            vertices: vertices.clone(),
+            instances: instances.clone(),
+        });
+
+        self.command_buffer.draw(vertices, instances);
+    }
+
+    /// # Safety
+    ///
+    /// Same requirements as `CommandBuffer::draw_indexed`.
+    pub unsafe fn draw_indexed(
+        &mut self,
+        indices: Range<u32>,
+        base_vertex: i32,
+        instances: Range<u32>,
+    ) {
+        self.record(|| RenderCommand::DrawIndexed {
+            indices: indices.clone(),
+            base_vertex,
+            instances: instances.clone(),
+        });
+
+        self.command_buffer
+            .draw_indexed(indices, base_vertex, instances);
+    }
+
+    /// Draws `draw_count` commands read from `buffer` starting at `offset`, see
+    /// [`DrawIndexedCommand`](crate::buffer::DrawIndexedCommand).
+    ///
+    /// # Safety
+    ///
+    /// Same requirements as `CommandBuffer::draw_indexed_indirect`.
+    pub unsafe fn draw_indexed_indirect(
+        &mut self,
+        buffer: (usize, &B::Buffer),
+        offset: u64,
+        draw_count: u32,
+        stride: u32,
+    ) {
+        self.record(|| RenderCommand::DrawIndexedIndirect {
+            buffer: buffer.0,
+            offset,
+            draw_count,
+            stride,
+        });
+
+        self.command_buffer
+            .draw_indexed_indirect(buffer.1, offset, draw_count, stride);
+    }
+
+    /// Hands the command buffer to `record` for commands the recorder doesn't know about.
+    pub fn custom(&mut self, record: impl FnOnce(&mut B::CommandBuffer)) {
+        self.record(|| RenderCommand::Custom);
+
+        record(self.command_buffer);
+    }
+
+    /// # Safety
+    ///
+    /// Same requirements as `CommandBuffer::end_render_pass`.
+    pub unsafe fn end_render_pass(&mut self) {
+        self.record(|| RenderCommand::EndRenderPass);
+
+        self.command_buffer.end_render_pass();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame_log(commands: Vec<RenderCommand>) -> FrameLog {
+        FrameLog { commands }
+    }
+
+    fn draw(vertex_count: u32) -> RenderCommand {
+        RenderCommand::Draw {
+            vertices: 0..vertex_count,
+            instances: 0..1,
+        }
+    }
+
+    #[test]
+    fn identical_logs_match() {
+        let log = frame_log(vec![RenderCommand::BindPipeline { pipeline: 0 }, draw(3)]);
+
+        assert_eq!(log.first_mismatch(&log.clone()), None);
+        assert_eq!(
+            FrameLog::default().first_mismatch(&FrameLog::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn first_differing_command_is_reported() {
+        let actual = frame_log(vec![
+            RenderCommand::BindPipeline { pipeline: 0 },
+            draw(3),
+            draw(6),
+        ]);
+        let expected = frame_log(vec![
+            RenderCommand::BindPipeline { pipeline: 0 },
+            draw(4),
+            draw(7),
+        ]);
+
+        assert_eq!(
+            actual.first_mismatch(&expected),
+            Some((1, Some(&draw(3)), Some(&draw(4))))
+        );
+    }
+
+    #[test]
+    fn missing_commands_are_reported() {
+        let short = frame_log(vec![draw(3)]);
+        let long = frame_log(vec![draw(3), RenderCommand::EndRenderPass]);
+
+        assert_eq!(
+            short.first_mismatch(&long),
+            Some((1, None, Some(&RenderCommand::EndRenderPass)))
+        );
+        assert_eq!(
+            long.first_mismatch(&short),
+            Some((1, Some(&RenderCommand::EndRenderPass), None))
+        );
+    }
+
+    #[test]
+    fn saved_log_loads_unchanged() {
+        let path =
+            std::env::temp_dir().join(format!("openglrust-command-log-{}.bin", std::process::id()));
+        let log = frame_log(vec![
+            RenderCommand::BeginRenderPass {
+                render_pass: 0,
+                clear_color: [0.0, 0.0, 0.0, 1.0],
+            },
+            RenderCommand::PushConstants {
+                pipeline_layout: 0,
+                offset: 64,
+                constants: vec![1, 2],
+            },
+            RenderCommand::DrawIndexed {
+                indices: 0..6,
+                base_vertex: 0,
+                instances: 0..1,
+            },
+            RenderCommand::Custom,
+            RenderCommand::EndRenderPass,
+        ]);
+
+        log.save(&path).unwrap();
+        let loaded = FrameLog::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, log);
+    }
+}
@@ -0,0 +1,294 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use gfx_hal::format::Format;
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+// Magic followed by the fixed size header.
+const DDS_HEADER_END: usize = 128;
+// Followed by the DX10 extension header when the pixel format's four character code is `DX10`.
+const DDS_DX10_HEADER_END: usize = DDS_HEADER_END + 20;
+
+const KTX2_IDENTIFIER: &[u8; 12] = &[
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+// Offset of the first entry of the level index, which describes the base level.
+const KTX2_LEVEL_INDEX: usize = 80;
+
+/// Top mip level of a block compressed texture, read from a DDS or KTX2 file without
+/// decompressing it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressedImage {
+    pub width: u32,
+    pub height: u32,
+    /// Formats the blocks can be sampled as, in order of preference. DDS files without a DX10
+    /// header don't say whether their colors are sRGB, so they list both interpretations.
+    pub formats: Vec<Format>,
+    /// Blocks of the top mip level, row by row.
+    pub data: Vec<u8>,
+}
+
+impl CompressedImage {
+    /// Parses a DDS or KTX2 file, detected from its magic bytes.
+    pub fn parse(bytes: &[u8]) -> Result<Self, CompressedImageError> {
+        if bytes.starts_with(DDS_MAGIC) {
+            parse_dds(bytes)
+        } else if bytes.starts_with(KTX2_IDENTIFIER) {
+            parse_ktx2(bytes)
+        } else {
+            Err(CompressedImageError::UnknownContainer)
+        }
+    }
+}
+
+/// Returned when a file can't be read as a [`CompressedImage`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompressedImageError {
+    /// The file is neither a DDS nor a KTX2 file.
+    UnknownContainer,
+    /// The file ends before its header or the data of its top mip level.
+    Truncated,
+    /// The file has no width or height.
+    EmptyExtent,
+    /// The file's pixel format isn't one of the supported block compressed formats, this holds
+    /// the four character code or DXGI format of DDS files and the `VkFormat` of KTX2 files.
+    UnknownFormat(u32),
+    /// KTX2 files with supercompression, such as Basis Universal, need transcoding first.
+    Supercompressed(u32),
+}
+
+impl fmt::Display for CompressedImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompressedImageError::UnknownContainer => write!(f, "Not a DDS or KTX2 file"),
+            CompressedImageError::Truncated => write!(f, "File is truncated"),
+            CompressedImageError::EmptyExtent => write!(f, "Image has no pixels"),
+            CompressedImageError::UnknownFormat(format) => {
+                write!(f, "Pixel format {:#x} isn't block compressed", format)
+            }
+            CompressedImageError::Supercompressed(scheme) => {
+                write!(f, "Supercompression scheme {} isn't supported", scheme)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CompressedImageError {}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u32, CompressedImageError> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|field| u32::from_le_bytes([field[0], field[1], field[2], field[3]]))
+        .ok_or(CompressedImageError::Truncated)
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Result<u64, CompressedImageError> {
+    Ok(u64::from(u32_at(bytes, offset)?) | u64::from(u32_at(bytes, offset + 4)?) << 32)
+}
+
+// Size in bytes of the top mip level, in whole blocks.
+fn level_size(width: u32, height: u32, format: Format) -> usize {
+    let desc = format.surface_desc();
+    let (block_width, block_height) = (u32::from(desc.dim.0), u32::from(desc.dim.1));
+    let blocks = width.div_ceil(block_width) * height.div_ceil(block_height);
+
+    blocks as usize * usize::from(desc.bits / 8)
+}
+
+fn image(
+    width: u32,
+    height: u32,
+    formats: Vec<Format>,
+    bytes: &[u8],
+    data_offset: usize,
+) -> Result<CompressedImage, CompressedImageError> {
+    if width == 0 || height == 0 {
+        return Err(CompressedImageError::EmptyExtent);
+    }
+
+    let size = level_size(width, height, formats[0]);
+    let data = bytes
+        .get(data_offset..)
+        .and_then(|data| data.get(..size))
+        .ok_or(CompressedImageError::Truncated)?;
+
+    Ok(CompressedImage {
+        width,
+        height,
+        formats,
+        data: data.to_vec(),
+    })
+}
+
+fn parse_dds(bytes: &[u8]) -> Result<CompressedImage, CompressedImageError> {
+    let height = u32_at(bytes, 12)?;
+    let width = u32_at(bytes, 16)?;
+    let four_cc = u32_at(bytes, 84)?;
+
+    let (formats, data_offset) = match &four_cc.to_le_bytes() {
+        b"DXT1" => (
+            vec![Format::Bc1RgbaSrgb, Format::Bc1RgbaUnorm],
+            DDS_HEADER_END,
+        ),
+        b"DXT3" => (vec![Format::Bc2Srgb, Format::Bc2Unorm], DDS_HEADER_END),
+        b"DXT5" => (vec![Format::Bc3Srgb, Format::Bc3Unorm], DDS_HEADER_END),
+        b"DX10" => {
+            let dxgi_format = u32_at(bytes, DDS_HEADER_END)?;
+            let format = match dxgi_format {
+                71 => Format::Bc1RgbaUnorm,
+                72 => Format::Bc1RgbaSrgb,
+                74 => Format::Bc2Unorm,
+                75 => Format::Bc2Srgb,
+                77 => Format::Bc3Unorm,
+                78 => Format::Bc3Srgb,
+                98 => Format::Bc7Unorm,
+                99 => Format::Bc7Srgb,
+                _ => return Err(CompressedImageError::UnknownFormat(dxgi_format)),
+            };
+
+            (vec![format], DDS_DX10_HEADER_END)
+        }
+        _ => return Err(CompressedImageError::UnknownFormat(four_cc)),
+    };
+
+    image(width, height, formats, bytes, data_offset)
+}
+
+fn parse_ktx2(bytes: &[u8]) -> Result<CompressedImage, CompressedImageError> {
+    let vk_format = u32_at(bytes, 12)?;
+    let width = u32_at(bytes, 20)?;
+    let height = u32_at(bytes, 24)?;
+    let supercompression = u32_at(bytes, 44)?;
+
+    if supercompression != 0 {
+        return Err(CompressedImageError::Supercompressed(supercompression));
+    }
+
+    let format = match vk_format {
+        133 => Format::Bc1RgbaUnorm,
+        134 => Format::Bc1RgbaSrgb,
+        135 => Format::Bc2Unorm,
+        136 => Format::Bc2Srgb,
+        137 => Format::Bc3Unorm,
+        138 => Format::Bc3Srgb,
+        145 => Format::Bc7Unorm,
+        146 => Format::Bc7Srgb,
+        147 => Format::Etc2R8g8b8Unorm,
+        148 => Format::Etc2R8g8b8Srgb,
+        149 => Format::Etc2R8g8b8a1Unorm,
+        150 => Format::Etc2R8g8b8a1Srgb,
+        151 => Format::Etc2R8g8b8a8Unorm,
+        152 => Format::Etc2R8g8b8a8Srgb,
+        157 => Format::Astc4x4Unorm,
+        158 => Format::Astc4x4Srgb,
+        165 => Format::Astc6x6Unorm,
+        166 => Format::Astc6x6Srgb,
+        171 => Format::Astc8x8Unorm,
+        172 => Format::Astc8x8Srgb,
+        _ => return Err(CompressedImageError::UnknownFormat(vk_format)),
+    };
+
+    let data_offset = u64_at(bytes, KTX2_LEVEL_INDEX)?;
+    let data_offset = usize::try_from(data_offset).map_err(|_| CompressedImageError::Truncated)?;
+
+    image(width, height, vec![format], bytes, data_offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dds(four_cc: &[u8; 4], width: u32, height: u32, dx10: Option<u32>, data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0; DDS_HEADER_END];
+        bytes[..4].copy_from_slice(DDS_MAGIC);
+        bytes[4..8].copy_from_slice(&124u32.to_le_bytes());
+        bytes[12..16].copy_from_slice(&height.to_le_bytes());
+        bytes[16..20].copy_from_slice(&width.to_le_bytes());
+        bytes[84..88].copy_from_slice(four_cc);
+
+        if let Some(dxgi_format) = dx10 {
+            bytes.extend_from_slice(&dxgi_format.to_le_bytes());
+            bytes.extend_from_slice(&[0; 16]);
+        }
+
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn ktx2(vk_format: u32, width: u32, height: u32, data: &[u8]) -> Vec<u8> {
+        // Header and index, a single level entry, then a few padding bytes before the data.
+        let data_offset = KTX2_LEVEL_INDEX + 24 + 8;
+
+        let mut bytes = vec![0; data_offset];
+        bytes[..12].copy_from_slice(KTX2_IDENTIFIER);
+        bytes[12..16].copy_from_slice(&vk_format.to_le_bytes());
+        bytes[20..24].copy_from_slice(&width.to_le_bytes());
+        bytes[24..28].copy_from_slice(&height.to_le_bytes());
+        bytes[40..44].copy_from_slice(&1u32.to_le_bytes());
+        bytes[80..88].copy_from_slice(&(data_offset as u64).to_le_bytes());
+        bytes[88..96].copy_from_slice(&(data.len() as u64).to_le_bytes());
+
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn dds_without_dx10_header_lists_srgb_first() {
+        // 6x4 pixels round up to two 16 byte blocks, the trailing bytes are the next mip level.
+        let data: Vec<u8> = (0..40).collect();
+        let image = CompressedImage::parse(&dds(b"DXT5", 6, 4, None, &data)).unwrap();
+
+        assert_eq!(image.width, 6);
+        assert_eq!(image.height, 4);
+        assert_eq!(image.formats, [Format::Bc3Srgb, Format::Bc3Unorm]);
+        assert_eq!(image.data, &data[..32]);
+    }
+
+    #[test]
+    fn dds_dx10_header_names_the_format() {
+        let data = [7; 16];
+        let image = CompressedImage::parse(&dds(b"DX10", 4, 4, Some(98), &data)).unwrap();
+
+        assert_eq!(image.formats, [Format::Bc7Unorm]);
+        assert_eq!(image.data, data);
+
+        assert_eq!(
+            CompressedImage::parse(&dds(b"DX10", 4, 4, Some(28), &data)),
+            Err(CompressedImageError::UnknownFormat(28))
+        );
+    }
+
+    #[test]
+    fn ktx2_data_is_read_from_the_level_index() {
+        // ASTC 8x8 blocks are 16 bytes, so 9x8 pixels take two blocks.
+        let data: Vec<u8> = (0..32).collect();
+        let image = CompressedImage::parse(&ktx2(171, 9, 8, &data)).unwrap();
+
+        assert_eq!(image.width, 9);
+        assert_eq!(image.formats, [Format::Astc8x8Unorm]);
+        assert_eq!(image.data, data);
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        assert_eq!(
+            CompressedImage::parse(b"\x89PNG\r\n\x1a\n"),
+            Err(CompressedImageError::UnknownContainer)
+        );
+        assert_eq!(
+            CompressedImage::parse(&dds(b"DXT1", 8, 8, None, &[0; 24])),
+            Err(CompressedImageError::Truncated)
+        );
+        assert_eq!(
+            CompressedImage::parse(&dds(b"DXT1", 0, 8, None, &[])),
+            Err(CompressedImageError::EmptyExtent)
+        );
+
+        let mut supercompressed = ktx2(145, 4, 4, &[0; 16]);
+        supercompressed[44..48].copy_from_slice(&1u32.to_le_bytes());
+        assert_eq!(
+            CompressedImage::parse(&supercompressed),
+            Err(CompressedImageError::Supercompressed(1))
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::HashSet;
+use winit::dpi::PhysicalPosition;
+use winit::event::{ElementState, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent};
+
+/// Keys and mouse buttons currently held down along with the cursor position, tracked from the
+/// window's events.
+#[derive(Debug, Clone, Default)]
+pub struct InputState {
+    pressed_keys: HashSet<VirtualKeyCode>,
+    pressed_buttons: HashSet<MouseButton>,
+    // `None` whilst the cursor is outside of the window.
+    cursor_position: Option<PhysicalPosition<i32>>,
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates the state from `event`. Returns whether it was an input event.
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        match *event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => match state {
+                ElementState::Pressed => self.pressed_keys.insert(key),
+                ElementState::Released => self.pressed_keys.remove(&key),
+            },
+            WindowEvent::MouseInput { state, button, .. } => match state {
+                ElementState::Pressed => self.pressed_buttons.insert(button),
+                ElementState::Released => self.pressed_buttons.remove(&button),
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = Some(position);
+                true
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor_position = None;
+                true
+            }
+            // Releases that happen whilst another window has focus are never reported.
+            WindowEvent::Focused(false) => {
+                self.pressed_keys.clear();
+                self.pressed_buttons.clear();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.pressed_keys.contains(&key)
+    }
+
+    pub fn is_button_pressed(&self, button: MouseButton) -> bool {
+        self.pressed_buttons.contains(&button)
+    }
+
+    pub fn pressed_keys(&self) -> impl Iterator<Item = VirtualKeyCode> + '_ {
+        self.pressed_keys.iter().copied()
+    }
+
+    /// Position of the cursor in physical pixels relative to the top left of the window, `None`
+    /// whilst it's outside of the window.
+    pub fn cursor_position(&self) -> Option<PhysicalPosition<i32>> {
+        self.cursor_position
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::event::{DeviceId, ModifiersState};
+
+    fn device_id() -> DeviceId {
+        // Only compared against, never passed to winit.
+        unsafe { DeviceId::dummy() }
+    }
+
+    #[allow(deprecated)]
+    fn key(key: VirtualKeyCode, state: ElementState) -> WindowEvent<'static> {
+        WindowEvent::KeyboardInput {
+            device_id: device_id(),
+            input: KeyboardInput {
+                scancode: 0,
+                state,
+                virtual_keycode: Some(key),
+                modifiers: ModifiersState::default(),
+            },
+            is_synthetic: false,
+        }
+    }
+
+    #[allow(deprecated)]
+    fn button(button: MouseButton, state: ElementState) -> WindowEvent<'static> {
+        WindowEvent::MouseInput {
+            device_id: device_id(),
+            state,
+            button,
+            modifiers: ModifiersState::default(),
+        }
+    }
+
+    #[allow(deprecated)]
+    fn cursor_moved(x: i32, y: i32) -> WindowEvent<'static> {
+        WindowEvent::CursorMoved {
+            device_id: device_id(),
+            position: PhysicalPosition::new(x, y),
+            modifiers: ModifiersState::default(),
+        }
+    }
+
+    #[test]
+    fn keys_and_buttons_are_held_until_released() {
+        let mut input = InputState::new();
+
+        assert!(input.handle_event(&key(VirtualKeyCode::W, ElementState::Pressed)));
+        assert!(input.handle_event(&button(MouseButton::Left, ElementState::Pressed)));
+        assert!(input.is_key_pressed(VirtualKeyCode::W));
+        assert!(input.is_button_pressed(MouseButton::Left));
+        assert_eq!(
+            input.pressed_keys().collect::<Vec<_>>(),
+            [VirtualKeyCode::W]
+        );
+
+        assert!(input.handle_event(&key(VirtualKeyCode::W, ElementState::Released)));
+        assert!(input.handle_event(&button(MouseButton::Left, ElementState::Released)));
+        assert!(!input.is_key_pressed(VirtualKeyCode::W));
+        assert!(!input.is_button_pressed(MouseButton::Left));
+    }
+
+    #[test]
+    fn cursor_leaving_resets_position() {
+        let mut input = InputState::new();
+        assert_eq!(input.cursor_position(), None);
+
+        assert!(input.handle_event(&cursor_moved(12, 34)));
+        assert_eq!(input.cursor_position(), Some(PhysicalPosition::new(12, 34)));
+
+        assert!(input.handle_event(&WindowEvent::CursorLeft {
+            device_id: device_id(),
+        }));
+        assert_eq!(input.cursor_position(), None);
+    }
+
+    #[test]
+    fn losing_focus_releases_everything() {
+        let mut input = InputState::new();
+        input.handle_event(&key(VirtualKeyCode::A, ElementState::Pressed));
+        input.handle_event(&button(MouseButton::Right, ElementState::Pressed));
+        input.handle_event(&cursor_moved(1, 2));
+
+        assert!(input.handle_event(&WindowEvent::Focused(false)));
+        assert!(!input.is_key_pressed(VirtualKeyCode::A));
+        assert!(!input.is_button_pressed(MouseButton::Right));
+        assert_eq!(input.cursor_position(), Some(PhysicalPosition::new(1, 2)));
+
+        assert!(!input.handle_event(&WindowEvent::Focused(true)));
+    }
+}
@@ -1,503 +1,2549 @@
-/// Requires shaderc library to be installed => https://github.com/google/shaderc
-extern crate shaderc;
-
-use gfx_hal::device::Device;
-use shaderc::ShaderKind;
-use std::error::Error;
-use std::mem::ManuallyDrop;
-
-fn main() -> Result<(), Box<dyn Error>> {
-    renderwindow()?;
-    Ok(())
-}
-
-pub struct GpuResources<B: gfx_hal::Backend> {
-    instance: B::Instance,
-    surface: B::Surface,
-    device: B::Device,
-    render_passes: Vec<B::RenderPass>,
-    pipeline_layouts: Vec<B::PipelineLayout>,
-    pipelines: Vec<B::GraphicsPipeline>,
-    command_pool: B::CommandPool,
-    submission_fence: B::Fence,
-    rendering_semaphore: B::Semaphore,
-}
-
-// Required because drop requires &mut self whilst destroy..() in gfx_hal takes exclusive ownership
-// of the object through self.
-struct ResourceHolder<B: gfx_hal::Backend>(ManuallyDrop<GpuResources<B>>);
-
-impl<B: gfx_hal::Backend> Drop for ResourceHolder<B> {
-    fn drop(&mut self) {
-        unsafe {
-            use gfx_hal::window::PresentationSurface;
-            use gfx_hal::Instance;
-
-            let GpuResources {
-                instance,
-                mut surface,
-                device,
-                render_passes,
-                pipeline_layouts,
-                pipelines,
-                command_pool,
-                submission_fence,
-                rendering_semaphore,
-            } = ManuallyDrop::take(&mut self.0);
-
-            device.destroy_semaphore(rendering_semaphore);
-            device.destroy_fence(submission_fence);
-            for pipeline in pipelines {
-                device.destroy_graphics_pipeline(pipeline);
-            }
-            for pipeline_layout in pipeline_layouts {
-                device.destroy_pipeline_layout(pipeline_layout);
-            }
-            for render_pass in render_passes {
-                device.destroy_render_pass(render_pass);
-            }
-            device.destroy_command_pool(command_pool);
-            surface.unconfigure_swapchain(&device);
-            instance.destroy_surface(surface);
-        }
-    }
-}
-fn read_shader(path: &str, default_options: bool) -> String {
-    use std::fs;
-
-    let mut objects: Vec<u8> = Vec::new();
-
-    if default_options {
-        objects.append(&mut fs::read("shaders/options.glsl").unwrap());
-        objects.append(&mut vec![0xA]);
-        objects.append(&mut fs::read(path).unwrap());
-
-        println!("{}", std::str::from_utf8(&objects).expect("Failed parsing"));
-
-        String::from_utf8(objects).expect("Failed to parse utf-8 sequence")
-    } else {
-        objects.append(&mut fs::read("shaders/vertex.glsl").unwrap());
-
-        String::from_utf8(objects).expect("Failed to parse utf-8 sequence")
-    }
-}
-
-/// Create a pipeline with the given layout and shaders.
-pub unsafe fn generate_pipeline<T: gfx_hal::Backend>(
-    device: &T::Device,
-    render_pass: &T::RenderPass,
-    pipeline_layout: &T::PipelineLayout,
-    vertex_shader: &str,
-    fragment_shader: &str,
-) -> T::GraphicsPipeline {
-    use gfx_hal::pass::Subpass;
-    use gfx_hal::pso::{
-        BlendState, ColorBlendDesc, ColorMask, EntryPoint, GraphicsPipelineDesc,
-        InputAssemblerDesc, Primitive, PrimitiveAssemblerDesc, Rasterizer, Specialization,
-    };
-
-    let vertex_shader_module = device
-        .create_shader_module(&compile_shader(vertex_shader, "Vertex", ShaderKind::Vertex))
-        .expect("Failed to create vertex shader module");
-
-    let fragment_shader_module = device
-        .create_shader_module(&compile_shader(
-            fragment_shader,
-            "Vertex",
-            ShaderKind::Fragment,
-        ))
-        .expect("Failed to create vertex shader module");
-
-    let vs_entry = EntryPoint {
-        entry: "pipeline()",
-        module: &vertex_shader_module,
-        specialization: Specialization::default(),
-    };
-
-    let fs_entry = EntryPoint {
-        entry: "pipeline()",
-        module: &fragment_shader_module,
-        specialization: Specialization::default(),
-    };
-
-    let primitive_assembler = PrimitiveAssemblerDesc::Vertex {
-        buffers: &[],
-        attributes: &[],
-        input_assembler: InputAssemblerDesc {
-            primitive: Primitive::TriangleList,
-            with_adjacency: false,
-            restart_index: None,
-        },
-        vertex: vs_entry,
-        tessellation: None,
-        geometry: None,
-    };
-
-    let mut pipeline_desc = GraphicsPipelineDesc::new(
-        primitive_assembler,
-        Rasterizer::FILL,
-        Some(fs_entry),
-        pipeline_layout,
-        Subpass {
-            index: 0,
-            main_pass: render_pass,
-        },
-    );
-
-    pipeline_desc.blender.targets.push(ColorBlendDesc {
-        mask: ColorMask::ALL,
-        blend: Some(BlendState::ALPHA),
-    });
-
-    let pipeline = device
-        .create_graphics_pipeline(&pipeline_desc, None)
-        .expect("Failed to create graphics pipeline");
-    device.destroy_shader_module(vertex_shader_module);
-    device.destroy_shader_module(fragment_shader_module);
-
-    pipeline
-}
-
-/// Compiles glsl shader to SPIR-V required for gfx_hal
-pub fn compile_shader(shader: &str, shader_name: &str, shader_kind: ShaderKind) -> Vec<u32> {
-    let mut compiler = shaderc::Compiler::new()
-        .unwrap_or_else(|| panic!("Failed to compile shader: {:?}", shader_kind));
-
-    let compiled_shader = compiler
-        .compile_into_spirv(shader, shader_kind, shader_name, "compile_shader()", None)
-        .unwrap_or_else(|error| panic!("Failed to compile shader: {}", error));
-
-    compiled_shader.as_binary().to_vec()
-}
-
-// TODO: create a struct to statically handle error's instead of boxing them.
-pub fn renderwindow() -> Result<(), Box<dyn Error>> {
-    use gfx_hal::{
-        window::{Extent2D, PresentationSurface, Surface},
-        Instance,
-    };
-
-    const WINDOW_TITLE: &str = "Sample text";
-    const WINDOW_SIZE: [u32; 2] = [2160, 3840];
-
-    let event_loop = winit::event_loop::EventLoop::new();
-
-    // Logical size represents the total number of pixels within a monitor.
-    // Physical size is the dimensions the OS will allocate and then apply a scale factor to.
-    // For example the OS might decide the physical size to be 1024 wide on a 2048px wide display,
-    // then apply a 2x scale factor. On the other hand if the display was to be 1024px; it'd
-    // apply a scale factor of 1x on the logical size.
-    let (logical_size, physical_size) = {
-        let dpi = event_loop.primary_monitor().scale_factor();
-        let logical: winit::dpi::LogicalSize<u32> = WINDOW_SIZE.into();
-
-        (logical, logical.to_physical(dpi))
-    };
-    let mut surface_extent = Extent2D {
-        width: physical_size.width,
-        height: physical_size.height,
-    };
-
-    let window = winit::window::WindowBuilder::new()
-        .with_title(WINDOW_TITLE)
-        .with_inner_size(logical_size)
-        .build(&event_loop)?;
-
-    let instance = backend::Instance::create(WINDOW_TITLE, 1).expect("Unsupported backend]");
-    let surface = unsafe { instance.create_surface(&window)? };
-    let adapter = instance.enumerate_adapters().remove(0);
-
-    println!("{:?}\n", adapter.info);
-
-    let (device, mut queue_group) = {
-        use gfx_hal::queue::QueueFamily;
-
-        let queue_family = adapter
-            .queue_families
-            .iter()
-            .find(|family| {
-                surface.supports_queue_family(family) && family.queue_type().supports_graphics()
-            })
-            .expect("No compatible queue family found");
-        let mut gpu = unsafe {
-            use gfx_hal::adapter::PhysicalDevice;
-            adapter
-                .physical_device
-                .open(&[(queue_family, &[1.0])], gfx_hal::Features::empty())
-                .expect("Failed to open device")
-        };
-
-        (gpu.device, gpu.queue_groups.pop().unwrap())
-    };
-
-    // command buffer => a structure of commands to render anything to the GPU, this is done
-    //                   via a command buffer. These command buffers are allocated from a
-    //                   command pool.
-    let (command_pool, mut command_buffer) = unsafe {
-        use gfx_hal::command::Level;
-        use gfx_hal::pool::{CommandPool, CommandPoolCreateFlags};
-
-        let mut command_pool = device
-            .create_command_pool(queue_group.family, CommandPoolCreateFlags::empty())
-            .expect("Out of memory");
-        let command_buffer = command_pool.allocate_one(Level::Primary);
-
-        (command_pool, command_buffer)
-    };
-
-    let surface_color_format = {
-        use gfx_hal::format::{ChannelType, Format};
-
-        let supported_formats = surface
-            .supported_formats(&adapter.physical_device)
-            .unwrap_or(vec![]);
-
-        let default_format = *supported_formats.get(0).unwrap_or(&Format::Rgba8Srgb);
-
-        supported_formats
-            .into_iter()
-            .find(|format| format.base_format().1 == ChannelType::Srgb)
-            .unwrap_or(default_format)
-    };
-
-    let render_pass = {
-        use gfx_hal::image::Layout;
-        use gfx_hal::pass::{
-            Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp, SubpassDesc,
-        };
-
-        let attachment = Attachment {
-            format: Some(surface_color_format),
-            samples: 1,
-            ops: AttachmentOps::new(AttachmentLoadOp::Clear, AttachmentStoreOp::Store),
-            stencil_ops: AttachmentOps::DONT_CARE,
-            layouts: Layout::Undefined..Layout::Present,
-        };
-
-        // colors: is refering to the first index of the list of attachments
-        // passed into create_render_pass.
-        let subpass = SubpassDesc {
-            colors: &[(0, Layout::ColorAttachmentOptimal)],
-            depth_stencil: None,
-            inputs: &[],
-            resolves: &[],
-            preserves: &[],
-        };
-
-        unsafe {
-            device
-                .create_render_pass(&[attachment], &[subpass], &[])
-                .expect("Out of memory")
-        }
-    };
-
-    // This defines textures and matrices required by the shaders, not required for
-    // the simple shaders i'm using right now.
-    let pipeline_layout = unsafe {
-        device
-            .create_pipeline_layout(&[], &[])
-            .expect("Out of memory")
-    };
-
-    let pipeline = unsafe {
-        generate_pipeline::<backend::Backend>(
-            &device,
-            &render_pass,
-            &pipeline_layout,
-            &read_shader("shaders/vertex.glsl", true)[..],
-            &read_shader("shaders/fragment.glsl", true)[..],
-        )
-    };
-
-    let submission_fence = device.create_fence(true).expect("Out of memory");
-    let rendering_semaphore = device.create_semaphore().expect("Out of memory");
-
-    let mut resource_container: ResourceHolder<backend::Backend> =
-        ResourceHolder(ManuallyDrop::new(GpuResources {
-            instance,
-            surface,
-            device,
-            command_pool,
-            render_passes: vec![render_pass],
-            pipeline_layouts: vec![pipeline_layout],
-            pipelines: vec![pipeline],
-            submission_fence,
-            rendering_semaphore,
-        }));
-
-    // The swapchain is a chain of images to render onto.
-    let mut configure_swapchain = true;
-
-    event_loop.run(move |event, _, control_flow| {
-        use winit::event::{Event, WindowEvent};
-        use winit::event_loop::ControlFlow;
-
-        match event {
-            // Handles all the events related to window updates
-            Event::WindowEvent { event, .. } => match event {
-                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                WindowEvent::Resized(updated) => {
-                    surface_extent = Extent2D {
-                        width: updated.width,
-                        height: updated.height,
-                    };
-                    configure_swapchain = true;
-                }
-                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                    surface_extent = Extent2D {
-                        width: new_inner_size.width,
-                        height: new_inner_size.height,
-                    };
-                    configure_swapchain = true;
-                }
-                _ => (),
-            },
-            // After input events, handle non-rendinering logic
-            Event::MainEventsCleared => {
-                window.request_redraw();
-            }
-            // TODO: Rendering logic implementation
-            Event::RedrawRequested(_) => {
-                // Timout to prevent 'hanging' of the image.
-                const TIMOUT: u64 = 1_000_000_000;
-
-                let resources: &mut GpuResources<_> = &mut resource_container.0;
-                //let render_pass = &resources.render_passes[0];
-                //let pipeline = &resources.pipelines[0];
-
-                unsafe {
-                    use gfx_hal::pool::CommandPool;
-
-                    resources
-                        .device
-                        .wait_for_fence(&resources.submission_fence, TIMOUT)
-                        .expect("Failed to wait for fence");
-
-                    resources
-                        .device
-                        .reset_fence(&resources.submission_fence)
-                        .expect("Out of memory");
-
-                    resources.command_pool.reset(false);
-                }
-
-                if configure_swapchain {
-                    use gfx_hal::pso as Dimensions;
-                    use gfx_hal::window::SwapchainConfig;
-
-                    let caps = resources.surface.capabilities(&adapter.physical_device);
-
-                    let swapchain_config =
-                        SwapchainConfig::from_caps(&caps, surface_color_format, surface_extent);
-
-                    /*
-                    MacOS fullscreen shutdown fix
-                    if caps.image_count.contains(&3) {
-                        swapchain_config.image_count = 3;
-                    }
-                    */
-
-                    surface_extent = swapchain_config.extent;
-
-                    unsafe {
-                        resources
-                            .surface
-                            .configure_swapchain(&resources.device, swapchain_config)
-                            .expect("Failed to configure swapchain");
-                    };
-
-                    configure_swapchain = false;
-
-                    let surface_image = unsafe {
-                        match resources.surface.acquire_image(TIMOUT) {
-                            Ok((image, _)) => image,
-                            Err(_) => {
-                                configure_swapchain = true;
-                                return;
-                            }
-                        }
-                    };
-
-                    let framebuffer = unsafe {
-                        use gfx_hal::image::Extent;
-                        use std::borrow::Borrow;
-
-                        resources
-                            .device
-                            .create_framebuffer(
-                                &resources.render_passes[0],
-                                vec![surface_image.borrow()],
-                                Extent {
-                                    width: surface_extent.width,
-                                    height: surface_extent.height,
-                                    depth: 1,
-                                },
-                            )
-                            .unwrap()
-                    };
-
-                    let viewport = Dimensions::Viewport {
-                        rect: Dimensions::Rect {
-                            x: 0,
-                            y: 0,
-                            w: surface_extent.width as i16,
-                            h: surface_extent.height as i16,
-                        },
-                        depth: 0.0..1.0,
-                    };
-
-                    unsafe {
-                        use gfx_hal::command::{
-                            ClearColor, ClearValue, CommandBuffer, CommandBufferFlags,
-                            SubpassContents,
-                        };
-                        use gfx_hal::queue::{CommandQueue, Submission};
-
-                        command_buffer.begin_primary(CommandBufferFlags::ONE_TIME_SUBMIT);
-
-                        command_buffer.set_viewports(0, &[viewport.clone()]);
-                        command_buffer.set_scissors(0, &[viewport.rect]);
-
-                        command_buffer.begin_render_pass(
-                            &resources.render_passes[0],
-                            &framebuffer,
-                            viewport.rect,
-                            &[ClearValue {
-                                color: ClearColor {
-                                    float32: [0.0, 0.0, 0.0, 1.0],
-                                },
-                            }],
-                            SubpassContents::Inline,
-                        );
-
-                        command_buffer.bind_graphics_pipeline(&resources.pipelines[0]);
-
-                        command_buffer.draw(0..0, 0..1);
-
-                        command_buffer.end_render_pass();
-                        command_buffer.finish();
-
-                        let submission = Submission {
-                            command_buffers: vec![&command_buffer],
-                            wait_semaphores: None,
-                            signal_semaphores: vec![&resources.rendering_semaphore],
-                        };
-
-                        queue_group.queues[0].submit(submission, Some(&resources.submission_fence));
-
-                        let result = queue_group.queues[0].present(
-                            &mut resources.surface,
-                            surface_image,
-                            Some(&resources.rendering_semaphore),
-                        );
-
-                        configure_swapchain |= result.is_err();
-
-                        resources.device.destroy_framebuffer(framebuffer);
-                    };
-                };
-            }
-            _ => (),
-        }
-    });
-}
+/// Requires shaderc library to be installed => https://github.com/google/shaderc
+extern crate shaderc;
+
+pub mod assets;
+pub mod attachment;
+pub mod buffer;
+pub mod command_log;
+pub mod compressed;
+pub mod input;
+pub mod math;
+pub mod renderer;
+pub mod scene;
+pub mod texture;
+pub mod timing;
+pub mod vertex;
+
+use assets::AssetResolver;
+use command_log::{CommandLogMode, FrameLog};
+use renderer::Renderer;
+use vertex::{Vertex, VertexLayout};
+
+use gfx_hal::device::Device;
+use shaderc::ShaderKind;
+use std::error::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Calls the generic function `$run` with the `gfx_hal::Backend` selected by `$kind`, an
+/// `Option<BackendKind>`, failing with [`RenderError::BackendNotCompiled`] when it isn't
+/// compiled in.
+macro_rules! with_backend {
+    ($kind:expr, $run:ident($($arg:expr),* $(,)?)) => {
+        match $kind.unwrap_or(BackendKind::COMPILED[0]) {
+            #[cfg(target_os = "macos")]
+            BackendKind::Metal => $run::<backend::Backend>($($arg),*),
+            #[cfg(windows)]
+            BackendKind::Dx12 => $run::<backend::Backend>($($arg),*),
+            #[cfg(all(unix, not(target_os = "macos")))]
+            BackendKind::Vulkan => $run::<backend::Backend>($($arg),*),
+            #[cfg(all(windows, feature = "vulkan"))]
+            BackendKind::Vulkan => $run::<gfx_backend_vulkan::Backend>($($arg),*),
+            requested => Err(RenderError::BackendNotCompiled(requested).into()),
+        }
+    };
+}
+
+/// Environment variable overriding [`RenderConfig::max_frames_in_flight`], useful to compare frame
+/// pacing without rebuilding.
+pub const FRAMES_IN_FLIGHT_VAR: &str = "FRAMES_IN_FLIGHT";
+
+/// Environment variable setting [`RenderConfig::adapter_name`], to pick a specific GPU on
+/// machines with several.
+pub const ADAPTER_VAR: &str = "ADAPTER";
+
+/// Environment variable enabling [`RenderConfig::enable_validation`] when set to `1`.
+pub const VALIDATION_VAR: &str = "VALIDATION";
+
+fn main() -> Result<ExitCode, Box<dyn Error>> {
+    let enable_validation = std::env::args().any(|arg| arg == "--validation")
+        || std::env::var(VALIDATION_VAR).is_ok_and(|value| value == "1");
+
+    // Validation messages are printed regardless of the log level.
+    if enable_validation {
+        set_validation_callback(
+            Box::new(print_validation_message),
+            Some(Box::new(StderrLogger::from_env())),
+        )?;
+    } else {
+        StderrLogger::from_env().install()?;
+    }
+
+    let mut config = RenderConfig {
+        clear_only: std::env::args().any(|arg| arg == "--clear-only"),
+        show_grid: std::env::args().any(|arg| arg == "--grid"),
+        pause_when_unfocused: !std::env::args().any(|arg| arg == "--render-unfocused"),
+        hot_reload: std::env::args().any(|arg| arg == "--hot-reload"),
+        enable_validation,
+        shader_cache: arg_value("--shader-cache").map(Into::into),
+        spin: std::env::args().any(|arg| arg == "--spin"),
+        quad: std::env::args().any(|arg| arg == "--quad"),
+        indirect: std::env::args().any(|arg| arg == "--indirect"),
+        texture: arg_value("--texture").map(Into::into),
+        present_mode: arg_value("--present-mode")
+            .map(|name| parse_present_mode(&name))
+            .transpose()?
+            .unwrap_or(gfx_hal::window::PresentMode::FIFO),
+        backend: arg_value("--backend")
+            .map(|name| name.parse())
+            .transpose()?,
+        power_preference: if std::env::args().any(|arg| arg == "--low-power") {
+            PowerPreference::LowPower
+        } else {
+            PowerPreference::HighPerformance
+        },
+        command_log: if let Some(path) = arg_value("--record-commands") {
+            CommandLogMode::Record(path.into())
+        } else if let Some(path) = arg_value("--verify-commands") {
+            CommandLogMode::Verify(path.into())
+        } else {
+            CommandLogMode::Off
+        },
+        ..RenderConfig::default()
+    };
+
+    if let Ok(value) = std::env::var(FRAMES_IN_FLIGHT_VAR) {
+        config.max_frames_in_flight = value
+            .parse()
+            .map_err(|_| format!("Invalid {}: {}", FRAMES_IN_FLIGHT_VAR, value))?;
+    }
+
+    if let Ok(name) = std::env::var(ADAPTER_VAR) {
+        config.adapter_name = Some(name);
+    }
+
+    if let Some(value) = arg_value("--msaa") {
+        config.anti_alias = AntiAlias::Msaa(
+            value
+                .parse()
+                .map_err(|_| format!("Invalid sample count: {}", value))?,
+        );
+    } else if std::env::args().any(|arg| arg == "--fxaa") {
+        config.anti_alias = AntiAlias::Fxaa;
+    }
+
+    if let Some(path) = arg_value("--headless") {
+        render_to_png(config, &path)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    Ok(renderwindow(config)?)
+}
+
+/// Renders a single frame without opening a window and saves it as a PNG.
+pub fn render_to_png(config: RenderConfig, path: &str) -> Result<(), Box<dyn Error>> {
+    with_backend!(config.backend, save_frame(config, path))
+}
+
+fn save_frame<B: gfx_hal::Backend>(config: RenderConfig, path: &str) -> Result<(), Box<dyn Error>> {
+    const IMAGE_SIZE: [u32; 2] = [1280, 720];
+
+    let mut renderer = Renderer::<B>::headless(config)?;
+    let [width, height] = IMAGE_SIZE;
+    let pixels = renderer.render_to_image(width, height, renderer::HEADLESS_FORMAT)?;
+
+    image::save_buffer(path, &pixels, width, height, image::ColorType::Rgba8)?;
+    log::info!("Saved frame to {}", path);
+
+    Ok(())
+}
+
+/// Value following the command line flag `name`.
+fn arg_value(name: &str) -> Option<String> {
+    let mut args = std::env::args();
+    args.find(|arg| arg == name)?;
+    args.next()
+}
+
+/// Runtime options for the renderer.
+pub struct RenderConfig {
+    /// Only clear the framebuffer each frame, skipping pipeline binding and draws. This isolates
+    /// the swapchain/present overhead from the cost of the actual draw work.
+    pub clear_only: bool,
+    /// Color the framebuffer is cleared to at the start of every frame.
+    pub clear_color: [f32; 4],
+    /// Receives the validation/debug messages reported by the backend. When `None` they are
+    /// left to whatever `log` implementation the application installed.
+    ///
+    /// The backends only report these through the `log` crate, so the callback is installed as
+    /// the global logger with a [`StderrLogger`] receiving all other records. The renderer fails
+    /// to be created with [`RenderError::Logger`] if the application already installed a
+    /// logger, in which case it should call [`set_validation_callback`] with its logger as the
+    /// fallback instead of installing the logger itself.
+    pub validation_callback: Option<ValidationCallback>,
+    /// Report validation messages even without a `validation_callback`, printing them with
+    /// [`print_validation_message`] unless the application installed a logger. The validation
+    /// layers are only compiled into debug builds of the backend, so release builds have no
+    /// validation to report.
+    pub enable_validation: bool,
+    /// Draw the grid helper, which is hidden behind the scene's geometry. Toggled with G or
+    /// [`Renderer::show_grid`].
+    pub show_grid: bool,
+    pub grid: GridConfig,
+    /// Rasterizer state of the main pipeline.
+    pub rasterizer: RasterizerConfig,
+    pub power_preference: PowerPreference,
+    /// Only use an adapter whose name contains this, ignoring case, overriding the power
+    /// preference.
+    pub adapter_name: Option<String>,
+    /// Record or verify the commands issued during the first frame, for regression testing.
+    pub command_log: CommandLogMode,
+    /// Extra usage of the swapchain images, e.g. `TRANSFER_SRC` to copy from them. Color
+    /// attachment usage is always included.
+    pub swapchain_usage: gfx_hal::image::Usage,
+    /// How frames are queued for presenting, `IMMEDIATE` disables vsync. Falls back to `FIFO`,
+    /// which every surface supports, when the surface doesn't support it.
+    pub present_mode: gfx_hal::window::PresentMode,
+    /// Exposure and gamma applied by the main fragment shader.
+    pub color_adjustment: ColorAdjustment,
+    /// Draw an indexed quad instead of the triangle.
+    pub quad: bool,
+    /// Draw through `draw_indexed_indirect`, with the draw parameters read from a buffer of
+    /// [`buffer::DrawIndexedCommand`]s rather than recorded into the command buffer.
+    pub indirect: bool,
+    /// Image drawn onto the quad instead of the triangle, located through `assets`. DDS and KTX2
+    /// files are uploaded in a block compressed format the device supports.
+    pub texture: Option<std::path::PathBuf>,
+    /// Draw the edges of the triangles only, toggled with W. Falls back to filled triangles if
+    /// the device can't draw lines.
+    pub wireframe: bool,
+    /// Model-view-projection matrix applied to the vertices of the main pipeline.
+    pub transform: math::Mat4,
+    /// Draw a spinning planet with a moon orbiting it, to check the transforms of a
+    /// [`scene::SceneGraph`] are applied.
+    pub spin: bool,
+    /// Locates shaders and other assets on disk.
+    pub assets: AssetResolver,
+    pub window: WindowConfig,
+    /// Stop rendering whilst the window doesn't have focus.
+    pub pause_when_unfocused: bool,
+    pub custom_draw: Option<CustomDraw>,
+    /// Called after every keyboard or mouse event with the updated input state.
+    pub on_input: Option<InputCallback>,
+    /// How many frames the CPU may record whilst the GPU is still busy with previous ones.
+    /// Lower values reduce input latency, higher values improve throughput. It's clamped to
+    /// one less than the number of swapchain images, as waiting on more would deadlock.
+    pub max_frames_in_flight: usize,
+    /// Selected with `--msaa <samples>` or `--fxaa`, none by default.
+    pub anti_alias: AntiAlias,
+    /// Rebuild the pipelines whenever their shaders are modified on disk.
+    pub hot_reload: bool,
+    /// Directory compiled shaders are cached in, skipping shaderc for shaders that haven't
+    /// changed since they were last compiled.
+    pub shader_cache: Option<std::path::PathBuf>,
+    /// Backend [`renderwindow`] and [`render_to_png`] create the renderer with, `None` uses the
+    /// platform's default. Requesting one that isn't in [`BackendKind::COMPILED`] fails with
+    /// [`RenderError::BackendNotCompiled`].
+    pub backend: Option<BackendKind>,
+}
+
+impl RenderConfig {
+    /// The number of frames in flight that can be used with `swapchain_images`.
+    pub fn frames_in_flight(&self, swapchain_images: u32) -> usize {
+        let limit = swapchain_images.saturating_sub(1) as usize;
+        self.max_frames_in_flight.min(limit).max(1)
+    }
+}
+
+/// Records user commands into the frame's command buffer, for anything the renderer doesn't
+/// cover itself. The command buffer is the `CommandBuffer` of the renderer's backend, which the
+/// callback downcasts it to. It's called once per frame after the built-in draws, where:
+///
+/// * The main render pass is active in its first subpass with inline contents, so the commands
+///   must be compatible with it and the render pass must not be ended or another one begun.
+/// * The viewport and scissor cover the whole surface.
+/// * Whatever pipeline was bound last is still bound, so the callback has to bind its own
+///   pipeline and any other state it relies on.
+/// * Resources used by the commands must stay alive until the frame's submission fence is
+///   signaled, which is waited on before the next frame is recorded.
+pub type CustomDraw = Box<dyn FnMut(&mut dyn std::any::Any)>;
+
+pub type InputCallback = Box<dyn FnMut(&input::InputState)>;
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            clear_only: false,
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            validation_callback: None,
+            enable_validation: false,
+            show_grid: false,
+            grid: GridConfig::default(),
+            rasterizer: RasterizerConfig::default(),
+            power_preference: PowerPreference::default(),
+            adapter_name: None,
+            command_log: CommandLogMode::default(),
+            swapchain_usage: gfx_hal::image::Usage::COLOR_ATTACHMENT,
+            present_mode: gfx_hal::window::PresentMode::FIFO,
+            color_adjustment: ColorAdjustment::default(),
+            quad: false,
+            indirect: false,
+            wireframe: false,
+            texture: None,
+            transform: math::IDENTITY,
+            spin: false,
+            assets: AssetResolver::from_env(),
+            window: WindowConfig::default(),
+            pause_when_unfocused: true,
+            custom_draw: None,
+            on_input: None,
+            max_frames_in_flight: 2,
+            anti_alias: AntiAlias::default(),
+            hot_reload: false,
+            shader_cache: None,
+            backend: None,
+        }
+    }
+}
+
+/// The swapchain image usage requested by the user, restricted to what the surface supports.
+fn swapchain_usage(
+    requested: gfx_hal::image::Usage,
+    caps: &gfx_hal::window::SurfaceCapabilities,
+) -> gfx_hal::image::Usage {
+    let requested = requested | gfx_hal::image::Usage::COLOR_ATTACHMENT;
+    let unsupported = requested - caps.usage;
+
+    if !unsupported.is_empty() {
+        log::warn!(
+            "Surface doesn't support swapchain image usage {:?}",
+            unsupported
+        );
+    }
+
+    requested & caps.usage
+}
+
+/// The present mode requested by the user if the surface supports it, otherwise `FIFO`.
+fn present_mode(
+    requested: gfx_hal::window::PresentMode,
+    caps: &gfx_hal::window::SurfaceCapabilities,
+) -> gfx_hal::window::PresentMode {
+    use gfx_hal::window::PresentMode;
+
+    if caps.present_modes.contains(requested) {
+        requested
+    } else {
+        log::warn!(
+            "Surface doesn't support present mode {:?}, falling back to FIFO",
+            requested
+        );
+
+        PresentMode::FIFO
+    }
+}
+
+fn parse_present_mode(name: &str) -> Result<gfx_hal::window::PresentMode, String> {
+    use gfx_hal::window::PresentMode;
+
+    match name.to_ascii_lowercase().as_str() {
+        "immediate" => Ok(PresentMode::IMMEDIATE),
+        "mailbox" => Ok(PresentMode::MAILBOX),
+        "fifo" => Ok(PresentMode::FIFO),
+        "relaxed" => Ok(PresentMode::RELAXED),
+        _ => Err(format!(
+            "Unknown present mode {}, expected immediate, mailbox, fifo or relaxed",
+            name
+        )),
+    }
+}
+
+/// Attributes applied to the window when it's created. Sizes are in logical pixels.
+#[derive(Debug, Clone)]
+pub struct WindowConfig {
+    pub title: String,
+    /// Initial size of the window's contents.
+    pub size: [u32; 2],
+    pub resizable: bool,
+    pub decorations: bool,
+    pub always_on_top: bool,
+    pub min_size: Option<[u32; 2]>,
+    pub max_size: Option<[u32; 2]>,
+}
+
+impl WindowConfig {
+    fn apply(&self, builder: winit::window::WindowBuilder) -> winit::window::WindowBuilder {
+        use winit::dpi::LogicalSize;
+
+        // Logical size represents the total number of pixels within a monitor.
+        // Physical size is the dimensions the OS will allocate and then apply a scale factor to.
+        // For example the OS might decide the physical size to be 1024 wide on a 2048px wide
+        // display, then apply a 2x scale factor. On the other hand if the display was to be
+        // 1024px; it'd apply a scale factor of 1x on the logical size.
+        let mut builder = builder
+            .with_title(self.title.as_str())
+            .with_inner_size(LogicalSize::<u32>::from(self.size))
+            .with_resizable(self.resizable)
+            .with_decorations(self.decorations)
+            .with_always_on_top(self.always_on_top);
+
+        if let Some(size) = self.min_size {
+            builder = builder.with_min_inner_size(LogicalSize::<u32>::from(size));
+        }
+
+        if let Some(size) = self.max_size {
+            builder = builder.with_max_inner_size(LogicalSize::<u32>::from(size));
+        }
+
+        builder
+    }
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            title: String::from("Sample text"),
+            size: [1280, 720],
+            resizable: true,
+            decorations: true,
+            always_on_top: false,
+            min_size: None,
+            max_size: None,
+        }
+    }
+}
+
+/// Exposure and gamma of the final image, adjustable at runtime with the arrow keys.
+///
+/// The swapchain uses an sRGB format whenever possible, so a gamma of 1.0 already results in
+/// correctly encoded output.
+#[derive(Debug, Clone, Copy)]
+pub struct ColorAdjustment {
+    pub exposure: f32,
+    pub gamma: f32,
+}
+
+/// Size in bytes of the matrix pushed to the main and grid vertex shaders, which the color
+/// adjustment and grid constants follow.
+pub const TRANSFORM_PUSH_CONSTANTS_SIZE: u32 = 16 * 4;
+
+impl ColorAdjustment {
+    /// Size in bytes of the push constants consumed by the main fragment shader.
+    pub const PUSH_CONSTANTS_SIZE: u32 = 2 * 4;
+
+    fn push_constants(&self) -> [u32; 2] {
+        [self.exposure.to_bits(), self.gamma.to_bits()]
+    }
+
+    /// Up/Down scale the exposure whilst Right/Left change the gamma. Returns whether the key
+    /// changed anything.
+    fn handle_key(&mut self, key: winit::event::VirtualKeyCode) -> bool {
+        use winit::event::VirtualKeyCode;
+
+        match key {
+            VirtualKeyCode::Up => self.exposure *= 1.1,
+            VirtualKeyCode::Down => self.exposure /= 1.1,
+            VirtualKeyCode::Right => self.gamma += 0.1,
+            VirtualKeyCode::Left => self.gamma = (self.gamma - 0.1).max(0.1),
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+impl Default for ColorAdjustment {
+    fn default() -> Self {
+        Self {
+            exposure: 1.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+/// Appearance of the grid helper, a ground plane at `y = 0` seen through a perspective camera.
+/// Sizes are in world units, the lines themselves are always a pixel wide.
+#[derive(Debug, Clone, Copy)]
+pub struct GridConfig {
+    /// Distance between neighbouring lines.
+    pub spacing: f32,
+    pub color: [f32; 4],
+    /// Half the width of the square covered by the grid, centered on the origin.
+    pub extent: f32,
+    /// World to view space matrix of the camera the grid is seen through, e.g. from
+    /// [`math::look_at`]. By default it looks down at the origin from above and behind.
+    pub view: math::Mat4,
+    /// Vertical field of view of the camera in radians.
+    pub fov_y: f32,
+}
+
+impl GridConfig {
+    /// Size in bytes of the push constants consumed by the grid shaders after the transform.
+    pub const PUSH_CONSTANTS_SIZE: u32 = 6 * 4;
+
+    /// Matrix transforming the grid into clip space, for a framebuffer of `width` by `height`.
+    pub fn view_projection(&self, width: u32, height: u32) -> math::Mat4 {
+        const NEAR: f32 = 0.1;
+
+        let aspect = width as f32 / height.max(1) as f32;
+        // Far enough to cover the corners of the grid from any camera within it.
+        let far = self.extent * 4.0;
+
+        math::mul(
+            &math::perspective(self.fov_y, aspect, NEAR, far),
+            &self.view,
+        )
+    }
+
+    fn push_constants(&self) -> [u32; 6] {
+        let [r, g, b, a] = self.color;
+
+        [
+            r.to_bits(),
+            g.to_bits(),
+            b.to_bits(),
+            a.to_bits(),
+            self.spacing.to_bits(),
+            self.extent.to_bits(),
+        ]
+    }
+}
+
+impl Default for GridConfig {
+    fn default() -> Self {
+        Self {
+            spacing: 1.0,
+            color: [0.5, 0.5, 0.5, 0.5],
+            extent: 50.0,
+            view: math::look_at([0.0, 2.0, 5.0], [0.0; 3], [0.0, 1.0, 0.0]),
+            fov_y: std::f32::consts::FRAC_PI_3,
+        }
+    }
+}
+
+/// The graphics APIs gfx-hal can run on, of which only [`BackendKind::COMPILED`] are usable by a
+/// given build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    Vulkan,
+    Dx12,
+    Metal,
+    Gl,
+}
+
+impl BackendKind {
+    /// The backends this binary was built with, the first one is the platform's default. Windows
+    /// builds include Vulkan next to DX12 with the `vulkan` feature.
+    pub const COMPILED: &'static [Self] = &[
+        #[cfg(target_os = "macos")]
+        BackendKind::Metal,
+        #[cfg(windows)]
+        BackendKind::Dx12,
+        #[cfg(any(all(unix, not(target_os = "macos")), all(windows, feature = "vulkan")))]
+        BackendKind::Vulkan,
+    ];
+}
+
+impl std::str::FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "vulkan" => Ok(BackendKind::Vulkan),
+            "dx12" => Ok(BackendKind::Dx12),
+            "metal" => Ok(BackendKind::Metal),
+            "gl" => Ok(BackendKind::Gl),
+            _ => Err(format!(
+                "Unknown backend {}, expected vulkan, dx12, metal or gl",
+                name
+            )),
+        }
+    }
+}
+
+/// How edges are smoothed in the rendered frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AntiAlias {
+    #[default]
+    None,
+    /// Render the color and depth attachments with this many samples per pixel, resolved into
+    /// the final image. Must be a power of two supported by the adapter.
+    Msaa(u8),
+    /// Render the scene into an offscreen image, which a full-screen FXAA pass filters into the
+    /// final image. Much cheaper than MSAA, at the cost of slightly blurring the whole frame.
+    Fxaa,
+}
+
+impl AntiAlias {
+    /// Samples per pixel of the scene's attachments.
+    pub fn samples(self) -> gfx_hal::image::NumSamples {
+        match self {
+            AntiAlias::Msaa(samples) => samples,
+            AntiAlias::None | AntiAlias::Fxaa => 1,
+        }
+    }
+}
+
+/// Which kind of GPU to prefer when several adapters are available, similar to WebGPU's
+/// `powerPreference`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PowerPreference {
+    /// Prefer a discrete GPU.
+    #[default]
+    HighPerformance,
+    /// Prefer an integrated GPU, saving battery on laptops with hybrid graphics.
+    LowPower,
+}
+
+impl PowerPreference {
+    // Lower is better.
+    fn rank(self, device_type: &gfx_hal::adapter::DeviceType) -> u8 {
+        use gfx_hal::adapter::DeviceType;
+
+        match (self, device_type) {
+            (PowerPreference::HighPerformance, DeviceType::DiscreteGpu) => 0,
+            (PowerPreference::HighPerformance, DeviceType::IntegratedGpu) => 1,
+            (PowerPreference::LowPower, DeviceType::IntegratedGpu) => 0,
+            (PowerPreference::LowPower, DeviceType::DiscreteGpu) => 1,
+            (_, DeviceType::VirtualGpu) => 2,
+            (_, DeviceType::Other) => 3,
+            (_, DeviceType::Cpu) => 4,
+        }
+    }
+}
+
+/// Picks the adapter best matching `preference`, keeping the enumeration order between adapters
+/// of the same type. Only adapters with a queue family that can render, and present to `surface`
+/// when given, are considered.
+///
+/// When `name` is given only adapters whose name contains it, ignoring case, are considered.
+pub fn select_adapter<B: gfx_hal::Backend>(
+    adapters: Vec<gfx_hal::adapter::Adapter<B>>,
+    preference: PowerPreference,
+    surface: Option<&B::Surface>,
+    name: Option<&str>,
+) -> Result<gfx_hal::adapter::Adapter<B>, RenderError> {
+    use gfx_hal::queue::QueueFamily;
+    use gfx_hal::window::Surface;
+
+    let candidates: Vec<_> = adapters
+        .iter()
+        .map(|adapter| AdapterCandidate {
+            name: &adapter.info.name,
+            device_type: adapter.info.device_type.clone(),
+            can_render: adapter.queue_families.iter().any(|family| {
+                family.queue_type().supports_graphics()
+                    && surface.is_none_or(|surface| surface.supports_queue_family(family))
+            }),
+        })
+        .collect();
+
+    let idx = select_candidate(&candidates, preference, name)?;
+    Ok(adapters.into_iter().nth(idx).unwrap())
+}
+
+// What `select_adapter` needs to know about an adapter.
+struct AdapterCandidate<'a> {
+    name: &'a str,
+    device_type: gfx_hal::adapter::DeviceType,
+    can_render: bool,
+}
+
+// Returns the index of the adapter `select_adapter` picks.
+fn select_candidate(
+    candidates: &[AdapterCandidate],
+    preference: PowerPreference,
+    name: Option<&str>,
+) -> Result<usize, RenderError> {
+    let lowercase_name = name.map(str::to_lowercase);
+
+    let mut named = candidates
+        .iter()
+        .enumerate()
+        .filter(|(_, candidate)| {
+            lowercase_name
+                .as_ref()
+                .is_none_or(|name| candidate.name.to_lowercase().contains(name))
+        })
+        .peekable();
+
+    if named.peek().is_none() {
+        return Err(match name {
+            Some(name) => RenderError::AdapterNotFound(name.to_string()),
+            None => RenderError::NoAdapter,
+        });
+    }
+
+    named
+        .filter(|(_, candidate)| candidate.can_render)
+        .min_by_key(|(idx, candidate)| (preference.rank(&candidate.device_type), *idx))
+        .map(|(idx, _)| idx)
+        .ok_or(RenderError::NoQueueFamily)
+}
+
+/// Environment variable holding the most verbose level printed by [`StderrLogger`], e.g.
+/// `RUST_LOG=debug`.
+pub const LOG_LEVEL_VAR: &str = "RUST_LOG";
+
+/// Prints log records to stderr, for applications that don't bring their own logger.
+#[derive(Debug, Clone, Copy)]
+pub struct StderrLogger {
+    pub level: log::LevelFilter,
+}
+
+impl StderrLogger {
+    /// Prints warnings and errors, or whatever level [`LOG_LEVEL_VAR`] is set to.
+    pub fn from_env() -> Self {
+        let level = match std::env::var(LOG_LEVEL_VAR) {
+            Ok(value) => value.parse().unwrap_or_else(|_| {
+                eprintln!("Invalid {}: {}", LOG_LEVEL_VAR, value);
+                log::LevelFilter::Warn
+            }),
+            Err(_) => log::LevelFilter::Warn,
+        };
+
+        Self { level }
+    }
+
+    /// Installs the logger as the global `log` implementation.
+    ///
+    /// Fails if a global logger has already been installed.
+    pub fn install(self) -> Result<(), log::SetLoggerError> {
+        log::set_boxed_logger(Box::new(self))?;
+        log::set_max_level(self.level);
+        Ok(())
+    }
+}
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{} [{}] {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// A single message reported by the validation layers (or the backend itself).
+#[derive(Debug, Clone, Copy)]
+pub struct ValidationMessage<'a> {
+    pub severity: log::Level,
+    /// The message type, e.g. `VALIDATION`, `PERFORMANCE` or the reporting layer's name.
+    pub kind: &'a str,
+    pub message: &'a str,
+}
+
+pub type ValidationCallback = Box<dyn Fn(&ValidationMessage) + Send + Sync>;
+
+// The backends only report their debug messages through the log crate, so the callback has to be
+// installed as the global logger. Records from any other target are passed to the fallback.
+struct ValidationLogger {
+    callback: ValidationCallback,
+    fallback: Option<Box<dyn log::Log>>,
+}
+
+impl ValidationLogger {
+    fn is_backend(metadata: &log::Metadata) -> bool {
+        metadata.target().starts_with("gfx_backend")
+    }
+}
+
+impl log::Log for ValidationLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        Self::is_backend(metadata)
+            || self
+                .fallback
+                .as_ref()
+                .is_some_and(|fallback| fallback.enabled(metadata))
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !Self::is_backend(record.metadata()) {
+            if let Some(fallback) = &self.fallback {
+                fallback.log(record);
+            }
+            return;
+        }
+
+        let text = record.args().to_string();
+        let text = text.trim();
+
+        // Debug utils messages look like "VALIDATION [id (number)] : message" whilst the older
+        // debug report extension uses "[layer] message".
+        let (kind, message) = match text.find('[') {
+            Some(0) => match text.find(']') {
+                Some(end) => (&text[1..end], text[end + 1..].trim_start()),
+                None => ("GENERAL", text),
+            },
+            Some(start) => (text[..start].trim_end(), &text[start..]),
+            None => ("GENERAL", text),
+        };
+
+        (self.callback)(&ValidationMessage {
+            severity: record.level(),
+            kind,
+            message,
+        });
+    }
+
+    fn flush(&self) {
+        if let Some(fallback) = &self.fallback {
+            fallback.flush();
+        }
+    }
+}
+
+/// Route all backend validation messages into `callback`, and every other log record into
+/// `fallback` when given.
+///
+/// Fails if a global logger has already been installed.
+pub fn set_validation_callback(
+    callback: ValidationCallback,
+    fallback: Option<Box<dyn log::Log>>,
+) -> Result<(), log::SetLoggerError> {
+    log::set_boxed_logger(Box::new(ValidationLogger { callback, fallback }))?;
+    log::set_max_level(log::LevelFilter::Trace);
+    Ok(())
+}
+
+/// Prints `message` to stderr, used when validation is enabled without a callback. It can't go
+/// through the `log` crate, as the callback is what's installed as the logger.
+pub fn print_validation_message(message: &ValidationMessage) {
+    eprintln!(
+        "{} [{}] {}",
+        message.severity, message.kind, message.message
+    );
+}
+
+/// Prelude prepended to all the built-in shaders.
+pub const SHADER_OPTIONS: &str = "shaders/options.glsl";
+
+/// Reads the GLSL source at `path`, preceded by the `prelude` file and a newline when given.
+///
+/// Lines of the form `#include "file"` are replaced by the source of `file`, located relative to
+/// the file including it. Files that end up including themselves are rejected.
+fn read_shader(assets: &AssetResolver, path: &str, prelude: Option<&str>) -> io::Result<String> {
+    let mut source = String::new();
+
+    if let Some(prelude) = prelude {
+        append_shader(
+            assets,
+            Path::new(prelude),
+            &mut source,
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )?;
+        source.push('\n');
+    }
+
+    append_shader(
+        assets,
+        Path::new(path),
+        &mut source,
+        &mut Vec::new(),
+        &mut Vec::new(),
+    )?;
+    log::debug!("{}:\n{}", path, source);
+
+    Ok(source)
+}
+
+/// `path` followed by every file it includes, directly or not.
+fn shader_files(assets: &AssetResolver, path: &str) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+
+    // The files read before an error are still returned, e.g. to watch them until it's fixed.
+    let _ = append_shader(
+        assets,
+        Path::new(path),
+        &mut String::new(),
+        &mut Vec::new(),
+        &mut files,
+    );
+
+    files
+}
+
+// Appends the source at `path` with its includes expanded to `source`. `including` holds the files
+// whose includes are being expanded and `files` collects every file read.
+fn append_shader(
+    assets: &AssetResolver,
+    path: &Path,
+    source: &mut String,
+    including: &mut Vec<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    if including.iter().any(|file| file == path) {
+        return Err(invalid(format!("{} includes itself", path.display())));
+    }
+
+    files.push(path.to_owned());
+    let text = String::from_utf8(assets.read(path)?)
+        .map_err(|_| invalid(format!("{} isn't valid UTF-8", path.display())))?;
+
+    including.push(path.to_owned());
+
+    for line in text.split_inclusive('\n') {
+        let name = match line.trim().strip_prefix("#include") {
+            Some(name) => name.trim(),
+            None => {
+                source.push_str(line);
+                continue;
+            }
+        };
+
+        let name = name
+            .strip_prefix('"')
+            .and_then(|name| name.strip_suffix('"'))
+            .ok_or_else(|| invalid(format!("{}: invalid {}", path.display(), line.trim())))?;
+        let included = path.parent().unwrap_or(Path::new("")).join(name);
+
+        append_shader(assets, &included, source, including, files)?;
+        if line.ends_with('\n') {
+            source.push('\n');
+        }
+    }
+
+    including.pop();
+
+    Ok(())
+}
+
+/// Face culling and winding order of a pipeline.
+///
+/// The default culls back faces and treats counter-clockwise triangles (as seen on screen) as
+/// front facing. Geometry wound the other way is culled entirely, so full-screen passes or
+/// meshes with inconsistent winding should use `Face::NONE`.
+#[derive(Debug, Clone, Copy)]
+pub struct RasterizerConfig {
+    pub cull_face: gfx_hal::pso::Face,
+    pub front_face: gfx_hal::pso::FrontFace,
+    /// Offsets the depth of each fragment, used to avoid z-fighting between coplanar surfaces.
+    pub depth_bias: Option<gfx_hal::pso::DepthBias>,
+    /// Whether triangles are filled or only have their edges or vertices drawn, which is useful
+    /// for debugging geometry.
+    pub polygon_mode: gfx_hal::pso::PolygonMode,
+}
+
+impl RasterizerConfig {
+    /// No culling, used for passes that draw a single full-screen triangle.
+    pub const NO_CULLING: Self = Self {
+        cull_face: gfx_hal::pso::Face::NONE,
+        front_face: gfx_hal::pso::FrontFace::CounterClockwise,
+        depth_bias: None,
+        polygon_mode: gfx_hal::pso::PolygonMode::Fill,
+    };
+
+    /// Pushes the depth of shadow casters away from the light to prevent shadow acne.
+    pub const SHADOW_DEPTH_BIAS: gfx_hal::pso::DepthBias = gfx_hal::pso::DepthBias {
+        const_factor: 1.25,
+        clamp: 0.0,
+        slope_factor: 1.75,
+    };
+
+    /// Pulls decals towards the camera so they win the depth test against the surface below.
+    pub const DECAL_DEPTH_BIAS: gfx_hal::pso::DepthBias = gfx_hal::pso::DepthBias {
+        const_factor: -1.0,
+        clamp: 0.0,
+        slope_factor: -1.0,
+    };
+
+    /// Device features that have to be enabled for this state to be valid.
+    pub fn required_features(&self) -> gfx_hal::Features {
+        let mut features = gfx_hal::Features::empty();
+
+        if self.depth_bias.is_some_and(|bias| bias.clamp != 0.0) {
+            features |= gfx_hal::Features::DEPTH_BIAS_CLAMP;
+        }
+        if self.polygon_mode != gfx_hal::pso::PolygonMode::Fill {
+            features |= gfx_hal::Features::NON_FILL_POLYGON_MODE;
+        }
+
+        features
+    }
+
+    /// Removes any state that isn't supported by a device with the given `features`.
+    pub fn restrict_to(mut self, features: gfx_hal::Features) -> Self {
+        if let Some(bias) = self.depth_bias.as_mut() {
+            if bias.clamp != 0.0 && !features.contains(gfx_hal::Features::DEPTH_BIAS_CLAMP) {
+                log::warn!("Device doesn't support depth bias clamping, ignoring the clamp");
+                bias.clamp = 0.0;
+            }
+        }
+
+        if self.polygon_mode != gfx_hal::pso::PolygonMode::Fill
+            && !features.contains(gfx_hal::Features::NON_FILL_POLYGON_MODE)
+        {
+            log::warn!(
+                "Device doesn't support {:?} polygon mode, filling polygons instead",
+                self.polygon_mode
+            );
+            self.polygon_mode = gfx_hal::pso::PolygonMode::Fill;
+        }
+
+        self
+    }
+
+    fn rasterizer(&self) -> gfx_hal::pso::Rasterizer {
+        use gfx_hal::pso::State;
+
+        gfx_hal::pso::Rasterizer {
+            cull_face: self.cull_face,
+            front_face: self.front_face,
+            depth_bias: self.depth_bias.map(State::Static),
+            polygon_mode: self.polygon_mode,
+            ..gfx_hal::pso::Rasterizer::FILL
+        }
+    }
+}
+
+impl Default for RasterizerConfig {
+    // Matches the winding of `TRIANGLE` and `QUAD_INDICES`, changing either requires changing the
+    // other or the built-in geometry gets culled.
+    fn default() -> Self {
+        Self {
+            cull_face: gfx_hal::pso::Face::BACK,
+            front_face: gfx_hal::pso::FrontFace::CounterClockwise,
+            depth_bias: None,
+            polygon_mode: gfx_hal::pso::PolygonMode::Fill,
+        }
+    }
+}
+
+/// Entry point used by all the built-in shaders.
+pub const DEFAULT_ENTRY_POINT: &str = "main";
+
+/// GLSL source of a single pipeline stage.
+#[derive(Debug, Clone, Copy)]
+pub struct ShaderSource<'a> {
+    /// Name shown in compiler diagnostics, usually the path the source was read from.
+    pub name: &'a str,
+    pub source: &'a str,
+}
+
+/// Create a pipeline with the given layout and shaders, reading vertices as described by
+/// `vertex_layout`. Both stages have to define `entry_point`, which is usually
+/// [`DEFAULT_ENTRY_POINT`].
+///
+/// Fragments are tested against the depth attachment with `depth_test`, or always pass when
+/// it's `None`.
+///
+/// # Safety
+///
+/// The render pass and pipeline layout must have been created by `device`.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn generate_pipeline<T: gfx_hal::Backend>(
+    device: &T::Device,
+    render_pass: &T::RenderPass,
+    pipeline_layout: &T::PipelineLayout,
+    vertex_shader: ShaderSource,
+    fragment_shader: ShaderSource,
+    entry_point: &str,
+    vertex_layout: &VertexLayout,
+    rasterizer: RasterizerConfig,
+    depth_test: Option<gfx_hal::pso::DepthTest>,
+    samples: gfx_hal::image::NumSamples,
+    shader_cache: Option<&std::path::Path>,
+) -> Result<T::GraphicsPipeline, RenderError> {
+    use gfx_hal::pass::Subpass;
+    use gfx_hal::pso::{
+        BlendState, ColorBlendDesc, ColorMask, EntryPoint, GraphicsPipelineDesc,
+        InputAssemblerDesc, Multisampling, Primitive, PrimitiveAssemblerDesc, Specialization,
+    };
+
+    let vertex_shader_module = create_shader_module::<T>(
+        device,
+        vertex_shader,
+        ShaderKind::Vertex,
+        entry_point,
+        shader_cache,
+    )?;
+
+    let fragment_shader_module = match create_shader_module::<T>(
+        device,
+        fragment_shader,
+        ShaderKind::Fragment,
+        entry_point,
+        shader_cache,
+    ) {
+        Ok(module) => module,
+        Err(error) => {
+            device.destroy_shader_module(vertex_shader_module);
+            return Err(error);
+        }
+    };
+
+    let vs_entry = EntryPoint {
+        entry: entry_point,
+        module: &vertex_shader_module,
+        specialization: Specialization::default(),
+    };
+
+    let fs_entry = EntryPoint {
+        entry: entry_point,
+        module: &fragment_shader_module,
+        specialization: Specialization::default(),
+    };
+
+    let primitive_assembler = PrimitiveAssemblerDesc::Vertex {
+        buffers: &vertex_layout.buffers,
+        attributes: &vertex_layout.attributes,
+        input_assembler: InputAssemblerDesc {
+            primitive: Primitive::TriangleList,
+            with_adjacency: false,
+            restart_index: None,
+        },
+        vertex: vs_entry,
+        tessellation: None,
+        geometry: None,
+    };
+
+    let mut pipeline_desc = GraphicsPipelineDesc::new(
+        primitive_assembler,
+        rasterizer.rasterizer(),
+        Some(fs_entry),
+        pipeline_layout,
+        Subpass {
+            index: 0,
+            main_pass: render_pass,
+        },
+    );
+
+    pipeline_desc.depth_stencil.depth = depth_test;
+
+    // Has to match the sample count of the render pass attachments.
+    if samples > 1 {
+        pipeline_desc.multisampling = Some(Multisampling {
+            rasterization_samples: samples,
+            sample_shading: None,
+            sample_mask: !0,
+            alpha_coverage: false,
+            alpha_to_one: false,
+        });
+    }
+
+    pipeline_desc.blender.targets.push(ColorBlendDesc {
+        mask: ColorMask::ALL,
+        blend: Some(BlendState::ALPHA),
+    });
+
+    let pipeline = device.create_graphics_pipeline(&pipeline_desc, None);
+    device.destroy_shader_module(vertex_shader_module);
+    device.destroy_shader_module(fragment_shader_module);
+
+    Ok(pipeline?)
+}
+
+// Compiles `shader` through the cache. A cached module the device rejects is compiled again, in
+// case the entry is corrupt in a way the cache can't tell.
+unsafe fn create_shader_module<T: gfx_hal::Backend>(
+    device: &T::Device,
+    shader: ShaderSource,
+    shader_kind: ShaderKind,
+    entry_point: &str,
+    shader_cache: Option<&std::path::Path>,
+) -> Result<T::ShaderModule, RenderError> {
+    let spirv = compile_shader_cached(
+        shader.source,
+        shader.name,
+        shader_kind,
+        entry_point,
+        shader_cache,
+    )?;
+
+    let cache_dir = match (device.create_shader_module(&spirv), shader_cache) {
+        (Ok(module), _) => return Ok(module),
+        (Err(error), None) => return Err(error.into()),
+        (Err(error), Some(cache_dir)) => {
+            log::warn!(
+                "Device rejected cached {}, compiling it again: {}",
+                shader.name,
+                error
+            );
+            cache_dir
+        }
+    };
+
+    let spirv = compile_shader(shader.source, shader.name, shader_kind, entry_point)?;
+    write_shader_cache(
+        &shader_cache_path(cache_dir, shader.source, shader_kind, entry_point),
+        shader.name,
+        &spirv,
+    );
+
+    Ok(device.create_shader_module(&spirv)?)
+}
+
+/// Returned when a shader couldn't be compiled to SPIR-V.
+#[derive(Debug)]
+pub enum ShaderError {
+    /// The shaderc compiler couldn't be created.
+    CompilerUnavailable(ShaderKind),
+    /// The source failed to compile, `error` holds the diagnostics.
+    Compilation {
+        kind: ShaderKind,
+        error: shaderc::Error,
+    },
+}
+
+impl ShaderError {
+    pub fn kind(&self) -> ShaderKind {
+        match *self {
+            ShaderError::CompilerUnavailable(kind) => kind,
+            ShaderError::Compilation { kind, .. } => kind,
+        }
+    }
+}
+
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ShaderError::CompilerUnavailable(kind) => {
+                write!(f, "Failed to create compiler for {:?} shader", kind)
+            }
+            ShaderError::Compilation { kind, error } => {
+                write!(f, "Failed to compile {:?} shader: {}", kind, error)
+            }
+        }
+    }
+}
+
+impl Error for ShaderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ShaderError::CompilerUnavailable(_) => None,
+            ShaderError::Compilation { error, .. } => Some(error),
+        }
+    }
+}
+
+/// Everything that can go wrong whilst setting up or running the renderer.
+#[derive(Debug)]
+pub enum RenderError {
+    Window(winit::error::OsError),
+    /// The validation callback couldn't be installed because a logger is already set.
+    Logger(log::SetLoggerError),
+    UnsupportedBackend,
+    BackendNotCompiled(BackendKind),
+    /// The adapter can't render with the [`AntiAlias::Msaa`] samples per pixel.
+    UnsupportedSampleCount(u8),
+    Surface(gfx_hal::window::InitError),
+    /// The backend didn't enumerate any adapters.
+    NoAdapter,
+    /// No adapter matches [`RenderConfig::adapter_name`].
+    AdapterNotFound(String),
+    /// None of the adapters has a queue family supporting both graphics and presenting to the
+    /// window's surface.
+    NoQueueFamily,
+    /// Presenting was requested from a headless renderer.
+    NoSurface,
+    /// An offscreen render was requested with a zero width or height.
+    EmptyExtent,
+    /// Offscreen renders can't use the format, either because the device can't render into it
+    /// or because a windowed renderer is bound to the format of its swapchain.
+    UnsupportedFormat(gfx_hal::format::Format),
+    Device(gfx_hal::device::CreationError),
+    /// A shader or its prelude couldn't be read.
+    ShaderRead(io::Error),
+    Shader(ShaderError),
+    /// The texture couldn't be read or decoded.
+    Texture(image::ImageError),
+    /// The DDS or KTX2 texture couldn't be parsed.
+    CompressedTexture(compressed::CompressedImageError),
+    /// The device can't sample any of the formats a compressed texture is available in.
+    NoCompressedFormat(Vec<gfx_hal::format::Format>),
+    /// The compiled SPIR-V was rejected by the device.
+    ShaderModule(gfx_hal::device::ShaderError),
+    Pipeline(gfx_hal::pso::CreationError),
+    /// The swapchain couldn't be (re)configured, which may succeed on a later attempt, e.g.
+    /// once a minimized window is restored.
+    Swapchain(gfx_hal::window::CreationError),
+    OutOfMemory(gfx_hal::device::OutOfMemory),
+    Buffer(gfx_hal::buffer::CreationError),
+    Image(gfx_hal::image::CreationError),
+    ImageView(gfx_hal::image::ViewCreationError),
+    /// Memory couldn't be bound to a buffer or image.
+    Bind(gfx_hal::device::BindError),
+    Map(gfx_hal::device::MapError),
+    /// None of the adapter's memory types has the properties a buffer or image needs.
+    NoMemoryType(gfx_hal::memory::Properties),
+    /// A device object such as a sampler couldn't be created.
+    Allocation(gfx_hal::device::AllocationError),
+    /// A descriptor set couldn't be allocated from its pool.
+    DescriptorAllocation(gfx_hal::pso::AllocationError),
+    DeviceLost,
+}
+
+impl std::fmt::Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RenderError::Window(error) => write!(f, "Failed to create window: {}", error),
+            RenderError::Logger(error) => {
+                write!(f, "Failed to install validation callback: {}", error)
+            }
+            RenderError::UnsupportedBackend => write!(f, "Backend isn't supported"),
+            RenderError::BackendNotCompiled(requested) => write!(
+                f,
+                "{:?} backend isn't compiled in, available are {:?}",
+                requested,
+                BackendKind::COMPILED
+            ),
+            RenderError::UnsupportedSampleCount(samples) => {
+                write!(f, "Adapter doesn't support {}x MSAA", samples)
+            }
+            RenderError::Surface(error) => write!(f, "{}", error),
+            RenderError::NoAdapter => write!(f, "No adapter found"),
+            RenderError::AdapterNotFound(name) => write!(f, "No adapter found matching {:?}", name),
+            RenderError::NoQueueFamily => write!(f, "No compatible queue family found"),
+            RenderError::NoSurface => write!(f, "Headless renderers can't present"),
+            RenderError::EmptyExtent => write!(f, "Can't render an image without pixels"),
+            RenderError::UnsupportedFormat(format) => {
+                write!(f, "Can't render images in {:?}", format)
+            }
+            RenderError::Device(error) => write!(f, "Failed to open device: {}", error),
+            RenderError::ShaderRead(error) => write!(f, "Failed to read shader: {}", error),
+            RenderError::Shader(error) => write!(f, "{}", error),
+            RenderError::Texture(error) => write!(f, "Failed to load texture: {}", error),
+            RenderError::CompressedTexture(error) => {
+                write!(f, "Failed to load compressed texture: {}", error)
+            }
+            RenderError::NoCompressedFormat(formats) => write!(
+                f,
+                "Device can't sample the compressed texture in any of its formats {:?}",
+                formats
+            ),
+            RenderError::ShaderModule(error) => {
+                write!(f, "Failed to create shader module: {}", error)
+            }
+            RenderError::Pipeline(error) => {
+                write!(f, "Failed to create graphics pipeline: {}", error)
+            }
+            RenderError::Swapchain(error) => write!(f, "Failed to configure swapchain: {}", error),
+            RenderError::OutOfMemory(error) => write!(f, "{}", error),
+            RenderError::Buffer(error) => write!(f, "Failed to create buffer: {}", error),
+            RenderError::Image(error) => write!(f, "Failed to create image: {}", error),
+            RenderError::ImageView(error) => write!(f, "Failed to create image view: {}", error),
+            RenderError::Bind(error) => write!(f, "Failed to bind memory: {}", error),
+            RenderError::Map(error) => write!(f, "Failed to map memory: {}", error),
+            RenderError::NoMemoryType(properties) => {
+                write!(f, "No memory type with {:?} found", properties)
+            }
+            RenderError::Allocation(error) => {
+                write!(f, "Failed to create device object: {}", error)
+            }
+            RenderError::DescriptorAllocation(error) => {
+                write!(f, "Failed to allocate descriptor set: {}", error)
+            }
+            RenderError::DeviceLost => write!(f, "Device lost"),
+        }
+    }
+}
+
+impl Error for RenderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RenderError::Window(error) => Some(error),
+            RenderError::Logger(error) => Some(error),
+            RenderError::Surface(error) => Some(error),
+            RenderError::Device(error) => Some(error),
+            RenderError::ShaderRead(error) => Some(error),
+            RenderError::Shader(error) => Some(error),
+            RenderError::Texture(error) => Some(error),
+            RenderError::CompressedTexture(error) => Some(error),
+            RenderError::ShaderModule(error) => Some(error),
+            RenderError::Pipeline(error) => Some(error),
+            RenderError::Swapchain(error) => Some(error),
+            RenderError::OutOfMemory(error) => Some(error),
+            RenderError::Buffer(error) => Some(error),
+            RenderError::Image(error) => Some(error),
+            RenderError::ImageView(error) => Some(error),
+            RenderError::Bind(error) => Some(error),
+            RenderError::Map(error) => Some(error),
+            RenderError::Allocation(error) => Some(error),
+            RenderError::DescriptorAllocation(error) => Some(error),
+            RenderError::UnsupportedBackend
+            | RenderError::BackendNotCompiled(_)
+            | RenderError::UnsupportedSampleCount(_)
+            | RenderError::NoAdapter
+            | RenderError::AdapterNotFound(_)
+            | RenderError::NoQueueFamily
+            | RenderError::NoMemoryType(_)
+            | RenderError::NoSurface
+            | RenderError::EmptyExtent
+            | RenderError::UnsupportedFormat(_)
+            | RenderError::NoCompressedFormat(_)
+            | RenderError::DeviceLost => None,
+        }
+    }
+}
+
+macro_rules! impl_from_error {
+    ($($error:ty => $variant:ident),* $(,)?) => {
+        $(impl From<$error> for RenderError {
+            fn from(error: $error) -> Self {
+                RenderError::$variant(error)
+            }
+        })*
+    };
+}
+
+impl_from_error!(
+    winit::error::OsError => Window,
+    log::SetLoggerError => Logger,
+    gfx_hal::window::InitError => Surface,
+    gfx_hal::device::CreationError => Device,
+    io::Error => ShaderRead,
+    ShaderError => Shader,
+    image::ImageError => Texture,
+    compressed::CompressedImageError => CompressedTexture,
+    gfx_hal::device::ShaderError => ShaderModule,
+    gfx_hal::pso::CreationError => Pipeline,
+    gfx_hal::window::CreationError => Swapchain,
+    gfx_hal::device::OutOfMemory => OutOfMemory,
+    gfx_hal::buffer::CreationError => Buffer,
+    gfx_hal::image::CreationError => Image,
+    gfx_hal::image::ViewCreationError => ImageView,
+    gfx_hal::device::BindError => Bind,
+    gfx_hal::device::MapError => Map,
+    gfx_hal::device::AllocationError => Allocation,
+    gfx_hal::pso::AllocationError => DescriptorAllocation,
+);
+
+impl From<gfx_hal::UnsupportedBackend> for RenderError {
+    fn from(_: gfx_hal::UnsupportedBackend) -> Self {
+        RenderError::UnsupportedBackend
+    }
+}
+
+impl From<gfx_hal::device::OomOrDeviceLost> for RenderError {
+    fn from(error: gfx_hal::device::OomOrDeviceLost) -> Self {
+        match error {
+            gfx_hal::device::OomOrDeviceLost::OutOfMemory(error) => RenderError::OutOfMemory(error),
+            gfx_hal::device::OomOrDeviceLost::DeviceLost(_) => RenderError::DeviceLost,
+        }
+    }
+}
+
+/// Compiles glsl shader to SPIR-V required for gfx_hal
+pub fn compile_shader(
+    shader: &str,
+    shader_name: &str,
+    shader_kind: ShaderKind,
+    entry_point: &str,
+) -> Result<Vec<u32>, ShaderError> {
+    let mut compiler =
+        shaderc::Compiler::new().ok_or(ShaderError::CompilerUnavailable(shader_kind))?;
+
+    let compiled_shader = compiler
+        .compile_into_spirv(shader, shader_kind, shader_name, entry_point, None)
+        .map_err(|error| ShaderError::Compilation {
+            kind: shader_kind,
+            error,
+        })?;
+
+    Ok(compiled_shader.as_binary().to_vec())
+}
+
+/// Same as [`compile_shader`], but loads the SPIR-V from `cache_dir` when the same source was
+/// compiled before. Cache entries are named after a hash of the source, shader kind and entry
+/// point, so editing any of them results in a recompile.
+///
+/// Failing to read or write the cache only falls back to compiling, it's never an error.
+pub fn compile_shader_cached(
+    shader: &str,
+    shader_name: &str,
+    shader_kind: ShaderKind,
+    entry_point: &str,
+    cache_dir: Option<&std::path::Path>,
+) -> Result<Vec<u32>, ShaderError> {
+    let cache_dir = match cache_dir {
+        Some(cache_dir) => cache_dir,
+        None => return compile_shader(shader, shader_name, shader_kind, entry_point),
+    };
+
+    let path = shader_cache_path(cache_dir, shader, shader_kind, entry_point);
+
+    if let Ok(bytes) = std::fs::read(&path) {
+        let spirv: Vec<u32> = bytes
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+
+        // Entries are renamed into place once complete, anything invalid is simply overwritten.
+        if bytes.len() % 4 == 0 && spirv.first() == Some(&SPIRV_MAGIC) {
+            log::debug!("Loaded {} from {}", shader_name, path.display());
+            return Ok(spirv);
+        }
+
+        log::warn!("Ignoring invalid cached shader {}", path.display());
+    }
+
+    let spirv = compile_shader(shader, shader_name, shader_kind, entry_point)?;
+    write_shader_cache(&path, shader_name, &spirv);
+
+    Ok(spirv)
+}
+
+// Writes the cache entry next to `path` first and renames it into place, so an interrupted write
+// never leaves a truncated entry behind.
+fn write_shader_cache(path: &std::path::Path, shader_name: &str, spirv: &[u32]) {
+    let bytes: Vec<u8> = spirv.iter().flat_map(|word| word.to_le_bytes()).collect();
+    let temp_path = path.with_extension(format!("spv.{}.tmp", std::process::id()));
+
+    let written = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&temp_path, bytes))
+        .and_then(|_| std::fs::rename(&temp_path, path));
+
+    if let Err(error) = written {
+        log::warn!("Failed to cache {}: {}", shader_name, error);
+        let _ = std::fs::remove_file(&temp_path);
+    }
+}
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+// Where the SPIR-V of `shader` is cached within `cache_dir`.
+fn shader_cache_path(
+    cache_dir: &std::path::Path,
+    shader: &str,
+    shader_kind: ShaderKind,
+    entry_point: &str,
+) -> std::path::PathBuf {
+    let key = format!("{:?}\0{}\0{}", shader_kind, entry_point, shader);
+    cache_dir.join(format!("{:016x}.spv", fnv1a(key.as_bytes())))
+}
+
+// 64-bit FNV-1a, unlike `DefaultHasher` it's guaranteed to stay the same across Rust versions,
+// which keeps the cache valid after updating the compiler.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// The triangle drawn by the main pipeline, wound counter-clockwise on screen so it survives the
+/// default back-face culling.
+pub const TRIANGLE: [Vertex; 3] = [
+    Vertex {
+        position: [0.0, -0.5],
+        color: [1.0, 0.0, 0.0],
+    },
+    Vertex {
+        position: [-0.5, 0.5],
+        color: [0.0, 1.0, 0.0],
+    },
+    Vertex {
+        position: [0.5, -0.5],
+        color: [0.0, 0.0, 1.0],
+    },
+];
+
+/// Indices drawing [`TRIANGLE`] in the order of its vertices, for indexed draws.
+pub const TRIANGLE_INDICES: [u16; 3] = [0, 1, 2];
+
+/// Corners of the quad drawn instead of the triangle with [`RenderConfig::quad`].
+pub const QUAD: [Vertex; 4] = [
+    Vertex {
+        position: [-0.5, -0.5],
+        color: [1.0, 0.0, 0.0],
+    },
+    Vertex {
+        position: [-0.5, 0.5],
+        color: [0.0, 1.0, 0.0],
+    },
+    Vertex {
+        position: [0.5, 0.5],
+        color: [0.0, 0.0, 1.0],
+    },
+    Vertex {
+        position: [0.5, -0.5],
+        color: [1.0, 1.0, 1.0],
+    },
+];
+
+/// Two triangles sharing the diagonal of [`QUAD`], with the same winding as [`TRIANGLE`].
+pub const QUAD_INDICES: [u16; 6] = [0, 1, 2, 0, 2, 3];
+
+/// Opens a window and renders into it until it's closed. Errors that stop the event loop are
+/// returned once the renderer has been destroyed, whilst a command log that couldn't be saved,
+/// loaded or verified results in a failure exit code.
+// Scene drawn with `RenderConfig::spin`. The planet and the moon's orbit rotate around the
+// system's root, so the moon follows the planet's rotation on top of its own.
+struct PlanetSystem {
+    graph: scene::SceneGraph,
+    root: scene::NodeId,
+    moon_orbit: scene::NodeId,
+}
+
+impl PlanetSystem {
+    fn new() -> Self {
+        use scene::Transform;
+
+        let scaled = |scale| Transform {
+            scale: [scale; 3],
+            ..Transform::IDENTITY
+        };
+
+        let mut graph = scene::SceneGraph::new();
+        let root = graph.add_root(Transform::IDENTITY);
+        graph.add_child(root, scaled(0.6));
+        let moon_orbit = graph.add_child(root, Transform::IDENTITY);
+        graph.add_child(
+            moon_orbit,
+            Transform {
+                translation: [0.7, 0.0, 0.0],
+                ..scaled(0.25)
+            },
+        );
+
+        Self {
+            graph,
+            root,
+            moon_orbit,
+        }
+    }
+
+    fn animate(&mut self, seconds: f32) {
+        let spin = |angle| scene::Transform {
+            rotation: math::quat_from_axis_angle([0.0, 0.0, 1.0], angle),
+            ..scene::Transform::IDENTITY
+        };
+
+        self.graph.set_local_transform(self.root, spin(seconds));
+        self.graph
+            .set_local_transform(self.moon_orbit, spin(seconds * 2.0));
+    }
+}
+
+/// Opens a window and renders into it until it's closed, with the backend selected by
+/// [`RenderConfig::backend`].
+pub fn renderwindow(config: RenderConfig) -> Result<ExitCode, RenderError> {
+    with_backend!(config.backend, run(config))
+}
+
+fn run<B: gfx_hal::Backend>(config: RenderConfig) -> Result<ExitCode, RenderError> {
+    use gfx_hal::window::Extent2D;
+    use winit::platform::desktop::EventLoopExtDesktop;
+
+    let mut event_loop = winit::event_loop::EventLoop::new();
+
+    let window = config
+        .window
+        .apply(winit::window::WindowBuilder::new())
+        .build(&event_loop)?;
+    // The frame rate is appended to the configured title.
+    let title = config.window.title.clone();
+
+    // How often the frame rate shown in the title is updated.
+    const TITLE_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+    let mut renderer = Renderer::<B>::new(&window, config)?;
+    let mut planet_system = PlanetSystem::new();
+    let start = std::time::Instant::now();
+    let mut frame_timer = timing::FrameTimer::new();
+    let mut input = input::InputState::new();
+    let mut title_updated = start;
+
+    // Set whilst the window is in the background and rendering is paused to save power.
+    let mut paused = false;
+    let mut result = Ok(());
+    let mut exit_code = ExitCode::SUCCESS;
+
+    event_loop.run_return(|event, _, control_flow| {
+        use winit::event::{ElementState, Event, KeyboardInput, WindowEvent};
+        use winit::event_loop::ControlFlow;
+
+        match event {
+            // Handles all the events related to window updates
+            Event::WindowEvent { event, .. } => {
+                if input.handle_event(&event) {
+                    if let Some(on_input) = renderer.config_mut().on_input.as_mut() {
+                        on_input(&input);
+                    }
+                }
+
+                match event {
+                    WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                    WindowEvent::Focused(focused) => {
+                        paused = !focused && renderer.config().pause_when_unfocused;
+
+                        if paused {
+                            frame_timer.reset();
+                        }
+                    }
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(key),
+                                ..
+                            },
+                        ..
+                    } => {
+                        use winit::event::VirtualKeyCode;
+
+                        let config = renderer.config_mut();
+
+                        match key {
+                            VirtualKeyCode::Escape => *control_flow = ControlFlow::Exit,
+                            VirtualKeyCode::W => config.wireframe = !config.wireframe,
+                            VirtualKeyCode::G => config.show_grid = !config.show_grid,
+                            _ => {
+                                if config.color_adjustment.handle_key(key) {
+                                    log::info!("{:?}", config.color_adjustment);
+                                }
+                            }
+                        }
+                    }
+                    WindowEvent::CursorMoved { .. } => {
+                        log::trace!("Cursor moved to {:?}", input.cursor_position());
+                    }
+                    WindowEvent::Resized(updated) => {
+                        renderer.resize(Extent2D {
+                            width: updated.width,
+                            height: updated.height,
+                        });
+                    }
+                    // Still fires for non-resizable windows, e.g. when moved to a monitor with a
+                    // different DPI, so the swapchain has to follow it regardless.
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        renderer.resize(Extent2D {
+                            width: new_inner_size.width,
+                            height: new_inner_size.height,
+                        });
+                    }
+                    _ => (),
+                }
+            }
+            // After input events, handle non-rendinering logic
+            Event::MainEventsCleared => {
+                if let Err(error) = renderer.reload_changed_shaders() {
+                    result = Err(error);
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+
+                if paused {
+                    *control_flow = ControlFlow::Wait;
+                } else {
+                    *control_flow = ControlFlow::Poll;
+                    window.request_redraw();
+                }
+            }
+            Event::RedrawRequested(_) if paused => (),
+            Event::RedrawRequested(_) => {
+                frame_timer.tick();
+
+                if title_updated.elapsed() >= TITLE_INTERVAL {
+                    if let Some(frame_time) = frame_timer.average() {
+                        window.set_title(&format!(
+                            "{} \u{2014} {:.0} fps / {:.1} ms",
+                            title,
+                            1.0 / frame_time.as_secs_f64(),
+                            frame_time.as_secs_f64() * 1000.0
+                        ));
+                        title_updated = std::time::Instant::now();
+                    }
+                }
+
+                if renderer.config().spin {
+                    planet_system.animate(start.elapsed().as_secs_f32());
+                    renderer.set_scene(&planet_system.graph);
+                }
+
+                match renderer.render_frame() {
+                    Ok(_) => (),
+                    // Retried on the next frame.
+                    Err(RenderError::Swapchain(error)) => {
+                        log::warn!("Failed to configure swapchain: {}", error)
+                    }
+                    Err(error) => {
+                        result = Err(error);
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                }
+
+                // Only the first frame is logged.
+                if let Some(recorded) = renderer.take_frame_log() {
+                    match &renderer.config().command_log {
+                        CommandLogMode::Record(path) => match recorded.save(path) {
+                            Ok(()) => log::info!("Saved command log to {}", path.display()),
+                            Err(error) => {
+                                log::error!(
+                                    "Failed to save command log to {}: {}",
+                                    path.display(),
+                                    error
+                                );
+                                exit_code = ExitCode::FAILURE;
+                                *control_flow = ControlFlow::Exit;
+                            }
+                        },
+                        CommandLogMode::Verify(path) => {
+                            *control_flow = ControlFlow::Exit;
+
+                            let expected = match FrameLog::load(path) {
+                                Ok(expected) => expected,
+                                Err(error) => {
+                                    log::error!(
+                                        "Failed to load command log from {}: {}",
+                                        path.display(),
+                                        error
+                                    );
+                                    exit_code = ExitCode::FAILURE;
+                                    return;
+                                }
+                            };
+
+                            match recorded.first_mismatch(&expected) {
+                                Some((idx, actual, expected)) => {
+                                    log::error!(
+                                        "Command {} differs, got {:?} but expected {:?}",
+                                        idx,
+                                        actual,
+                                        expected
+                                    );
+                                    exit_code = ExitCode::FAILURE;
+                                }
+                                None => log::info!("Command log matches {}", path.display()),
+                            }
+                        }
+                        CommandLogMode::Off => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    });
+
+    // The renderer has to be destroyed before the window it renders into.
+    drop(renderer);
+    result.map(|_| exit_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_glsl_fails_to_compile() {
+        let result = compile_shader(
+            "#version 450\nvoid main() { undefined_function(); }\n",
+            "invalid.glsl",
+            ShaderKind::Vertex,
+            DEFAULT_ENTRY_POINT,
+        );
+
+        match result {
+            Err(ShaderError::Compilation {
+                kind: ShaderKind::Vertex,
+                ..
+            }) => (),
+            other => panic!("Expected a compilation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    #[ignore = "requires a GPU"]
+    fn headless_triangle_is_rendered() {
+        const SIZE: u32 = 128;
+
+        let config = RenderConfig {
+            clear_color: [1.0, 1.0, 1.0, 1.0],
+            ..RenderConfig::default()
+        };
+        let mut renderer = Renderer::<backend::Backend>::headless(config).unwrap();
+        let pixels = renderer
+            .render_to_image(SIZE, SIZE, renderer::HEADLESS_FORMAT)
+            .unwrap();
+        let pixel = |position| pixel_at(&pixels, SIZE, position);
+
+        assert_eq!(pixel([-1.0, -1.0]), [255, 255, 255, 255]);
+
+        // Weighted 0.1, 0.1 and 0.8 between the vertices, well inside the triangle and close
+        // enough to the blue vertex for it to dominate the interpolated color.
+        let [a, b, c] = TRIANGLE.map(|vertex| vertex.position);
+        let position = [0, 1].map(|axis| 0.1 * a[axis] + 0.1 * b[axis] + 0.8 * c[axis]);
+        let [red, green, blue, alpha] = pixel(position);
+
+        assert!(
+            blue > 200 && red < 128 && green < 128 && alpha == 255,
+            "Expected a mostly blue pixel, got {:?}",
+            [red, green, blue, alpha]
+        );
+    }
+
+    #[test]
+    #[ignore = "requires a GPU"]
+    fn headless_quad_is_rendered() {
+        const SIZE: u32 = 128;
+
+        let config = RenderConfig {
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            quad: true,
+            ..RenderConfig::default()
+        };
+        let mut renderer = Renderer::<backend::Backend>::headless(config).unwrap();
+        let pixels = renderer
+            .render_to_image(SIZE, SIZE, renderer::HEADLESS_FORMAT)
+            .unwrap();
+
+        assert_eq!(pixel_at(&pixels, SIZE, [-0.9, -0.9]), [0, 0, 0, 255]);
+
+        // Slightly inside each corner, where the corner's color dominates the interpolation.
+        for vertex in QUAD {
+            let position = vertex.position.map(|coordinate| coordinate * 0.9);
+            let actual = pixel_at(&pixels, SIZE, position);
+
+            assert!(
+                (0..3).all(|channel| if vertex.color[channel] > 0.5 {
+                    actual[channel] > 200
+                } else {
+                    actual[channel] < 100
+                }) && actual[3] == 255,
+                "Expected {:?} near {:?}, got {:?}",
+                vertex.color,
+                vertex.position,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "requires a GPU"]
+    fn headless_indirect_draws_match_direct_draws() {
+        const SIZE: u32 = 64;
+
+        for quad in [false, true] {
+            let render = |indirect| {
+                let config = RenderConfig {
+                    clear_color: [0.0, 0.0, 0.0, 1.0],
+                    quad,
+                    indirect,
+                    ..RenderConfig::default()
+                };
+                let mut renderer = Renderer::<backend::Backend>::headless(config).unwrap();
+                renderer
+                    .render_to_image(SIZE, SIZE, renderer::HEADLESS_FORMAT)
+                    .unwrap()
+            };
+
+            assert!(render(true) == render(false), "quad: {}", quad);
+        }
+    }
+
+    #[test]
+    #[ignore = "requires a GPU"]
+    fn headless_fxaa_only_smooths_edges() {
+        const SIZE: u32 = 64;
+
+        let render = |anti_alias| {
+            let config = RenderConfig {
+                clear_color: [0.0, 0.0, 0.0, 1.0],
+                anti_alias,
+                ..RenderConfig::default()
+            };
+            let mut renderer = Renderer::<backend::Backend>::headless(config).unwrap();
+            renderer
+                .render_to_image(SIZE, SIZE, renderer::HEADLESS_FORMAT)
+                .unwrap()
+        };
+        let aliased = render(AntiAlias::None);
+        let smoothed = render(AntiAlias::Fxaa);
+
+        // Flat areas are left as is, only the triangle's edges are blended with the background.
+        assert_eq!(pixel_at(&smoothed, SIZE, [-0.9, -0.9]), [0, 0, 0, 255]);
+
+        let [a, b, c] = TRIANGLE.map(|vertex| vertex.position);
+        let center = [0, 1].map(|axis| (a[axis] + b[axis] + c[axis]) / 3.0);
+        let expected = pixel_at(&aliased, SIZE, center);
+        let actual = pixel_at(&smoothed, SIZE, center);
+        assert!(
+            expected
+                .iter()
+                .zip(&actual)
+                .all(|(expected, actual)| expected.abs_diff(*actual) <= 4),
+            "Expected {:?} at the center, got {:?}",
+            expected,
+            actual
+        );
+
+        assert!(aliased != smoothed, "FXAA didn't change any edge");
+    }
+
+    #[test]
+    #[ignore = "requires a GPU"]
+    fn headless_grid_is_rendered() {
+        const SIZE: u32 = 128;
+
+        let config = RenderConfig {
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            ..RenderConfig::default()
+        };
+        let mut renderer = Renderer::<backend::Backend>::headless(config).unwrap();
+        let lit_pixels = |renderer: &mut Renderer<backend::Backend>, rows: std::ops::Range<u32>| {
+            let pixels = renderer
+                .render_to_image(SIZE, SIZE, renderer::HEADLESS_FORMAT)
+                .unwrap();
+            let row_bytes = SIZE as usize * 4;
+
+            pixels[rows.start as usize * row_bytes..rows.end as usize * row_bytes]
+                .chunks_exact(4)
+                .filter(|pixel| pixel[..3] != [0, 0, 0])
+                .count()
+        };
+
+        // Below the triangle the ground plane is close to the default camera, above it there's
+        // only sky.
+        let ground = SIZE * 7 / 8..SIZE;
+        let sky = 0..SIZE / 16;
+
+        assert_eq!(lit_pixels(&mut renderer, ground.clone()), 0);
+
+        renderer.show_grid(true);
+        assert!(lit_pixels(&mut renderer, ground) > 0);
+        assert_eq!(lit_pixels(&mut renderer, sky), 0);
+    }
+
+    #[test]
+    #[ignore = "requires a GPU"]
+    fn empty_headless_render_is_rejected() {
+        let mut renderer = Renderer::<backend::Backend>::headless(RenderConfig::default()).unwrap();
+
+        for (width, height) in [(0, 16), (16, 0)] {
+            assert!(matches!(
+                renderer.render_to_image(width, height, renderer::HEADLESS_FORMAT),
+                Err(RenderError::EmptyExtent)
+            ));
+        }
+    }
+
+    // Pixel of a square image of `size` rendered by `render_to_image` containing the normalized
+    // device coordinates `position`, y points down.
+    fn pixel_at(pixels: &[u8], size: u32, [x, y]: [f32; 2]) -> [u8; 4] {
+        let column = ((x + 1.0) / 2.0 * size as f32) as usize;
+        let row = ((y + 1.0) / 2.0 * size as f32) as usize;
+        let start = (row * size as usize + column) * 4;
+        [
+            pixels[start],
+            pixels[start + 1],
+            pixels[start + 2],
+            pixels[start + 3],
+        ]
+    }
+
+    #[test]
+    #[ignore = "requires a GPU"]
+    fn headless_texture_is_sampled() {
+        const SIZE: u32 = 128;
+        // Row-major, top row first.
+        const TEXELS: [[u8; 4]; 4] = [
+            [255, 0, 0, 255],
+            [0, 255, 0, 255],
+            [0, 0, 255, 255],
+            [255, 255, 255, 255],
+        ];
+
+        let dir = temp_dir("texture");
+        let path = dir.join("checker.png");
+        image::RgbaImage::from_raw(2, 2, TEXELS.concat())
+            .unwrap()
+            .save(&path)
+            .unwrap();
+
+        let config = RenderConfig {
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            texture: Some(path),
+            ..RenderConfig::default()
+        };
+        let mut renderer = Renderer::<backend::Backend>::headless(config).unwrap();
+        let pixels = renderer
+            .render_to_image(SIZE, SIZE, renderer::HEADLESS_FORMAT)
+            .unwrap();
+        drop(renderer);
+
+        // The quad spans -0.5 to 0.5 with the texture stretched across it, so the texel centers
+        // are a quarter in from its edges.
+        for (idx, expected) in TEXELS.iter().enumerate() {
+            let position = [(idx % 2) as f32 * 0.5 - 0.25, (idx / 2) as f32 * 0.5 - 0.25];
+            let actual = pixel_at(&pixels, SIZE, position);
+
+            assert!(
+                actual
+                    .iter()
+                    .zip(expected)
+                    .all(|(&actual, &expected)| (actual as i16 - expected as i16).abs() < 16),
+                "Expected {:?} at {:?}, got {:?}",
+                expected,
+                position,
+                actual
+            );
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Twice the area of the triangle as defined by Vulkan's rasterization rules, positive for
+    // counter-clockwise triangles in framebuffer coordinates where y points down.
+    fn signed_area(positions: [[f32; 2]; 3]) -> f32 {
+        -(0..3)
+            .map(|idx| {
+                let [x0, y0] = positions[idx];
+                let [x1, y1] = positions[(idx + 1) % 3];
+                x0 * y1 - x1 * y0
+            })
+            .sum::<f32>()
+    }
+
+    // Event loop for tests, which run outside of the main thread.
+    fn test_event_loop() -> winit::event_loop::EventLoop<()> {
+        #[cfg(any(
+            target_os = "linux",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            target_os = "openbsd"
+        ))]
+        use winit::platform::unix::EventLoopExtUnix;
+        #[cfg(windows)]
+        use winit::platform::windows::EventLoopExtWindows;
+
+        #[cfg(not(target_os = "macos"))]
+        return winit::event_loop::EventLoop::new_any_thread();
+        #[cfg(target_os = "macos")]
+        return winit::event_loop::EventLoop::new();
+    }
+
+    #[test]
+    #[ignore = "requires a display"]
+    fn window_is_created_with_configured_size() {
+        use winit::dpi::{LogicalSize, PhysicalSize};
+
+        let config = WindowConfig {
+            size: [320, 240],
+            resizable: false,
+            min_size: Some([160, 120]),
+            max_size: Some([640, 480]),
+            ..WindowConfig::default()
+        };
+
+        let event_loop = test_event_loop();
+        let window = config
+            .apply(winit::window::WindowBuilder::new())
+            .build(&event_loop)
+            .unwrap();
+
+        let expected: PhysicalSize<u32> =
+            LogicalSize::<u32>::from(config.size).to_physical(window.scale_factor());
+        assert_eq!(window.inner_size(), expected);
+    }
+
+    #[test]
+    fn moon_orbits_with_planet_rotation() {
+        let mut planet_system = PlanetSystem::new();
+        // The moon's orbit turns twice as fast as the planet, on top of it, adding up to a
+        // quarter turn.
+        planet_system.animate(std::f32::consts::FRAC_PI_6);
+
+        let mut matrices = Vec::new();
+        planet_system.graph.leaf_world_matrices(&mut matrices);
+        assert_eq!(matrices.len(), 2);
+        let (planet, moon) = (matrices[0], matrices[1]);
+
+        assert_eq!(planet[3], [0.0, 0.0, 0.0, 1.0]);
+        assert!(moon[3][0].abs() < 1e-5 && (moon[3][1] - 0.7).abs() < 1e-5);
+    }
+
+    fn backend_name<B: gfx_hal::Backend>() -> Result<&'static str, RenderError> {
+        Ok(std::any::type_name::<B>())
+    }
+
+    #[test]
+    fn compiled_backends_are_dispatched() {
+        let default = with_backend!(None::<BackendKind>, backend_name()).unwrap();
+        let first = with_backend!(Some(BackendKind::COMPILED[0]), backend_name()).unwrap();
+        assert_eq!(default, first);
+
+        for &kind in BackendKind::COMPILED {
+            assert!(with_backend!(Some(kind), backend_name()).is_ok());
+        }
+
+        assert!(matches!(
+            with_backend!(Some(BackendKind::Gl), backend_name()),
+            Err(RenderError::BackendNotCompiled(BackendKind::Gl))
+        ));
+    }
+
+    #[test]
+    fn only_msaa_multisamples_the_scene() {
+        assert_eq!(AntiAlias::None.samples(), 1);
+        assert_eq!(AntiAlias::Msaa(4).samples(), 4);
+        assert_eq!(AntiAlias::Fxaa.samples(), 1);
+    }
+
+    #[test]
+    fn frames_in_flight_leave_an_image_to_present() {
+        let frames_in_flight = |max_frames_in_flight, swapchain_images| {
+            RenderConfig {
+                max_frames_in_flight,
+                ..RenderConfig::default()
+            }
+            .frames_in_flight(swapchain_images)
+        };
+
+        for swapchain_images in 1..=3 {
+            assert_eq!(frames_in_flight(0, swapchain_images), 1);
+        }
+        assert_eq!(frames_in_flight(5, 1), 1);
+        assert_eq!(frames_in_flight(5, 2), 1);
+        assert_eq!(frames_in_flight(5, 3), 2);
+    }
+
+    #[test]
+    fn default_rasterizer_keeps_built_in_geometry() {
+        use gfx_hal::pso::{Face, FrontFace, PolygonMode};
+
+        let rasterizer = RasterizerConfig::default();
+        assert_eq!(rasterizer.cull_face, Face::BACK);
+        assert_eq!(rasterizer.front_face, FrontFace::CounterClockwise);
+        assert_eq!(rasterizer.depth_bias, None);
+        assert_eq!(rasterizer.polygon_mode, PolygonMode::Fill);
+        assert!(rasterizer.required_features().is_empty());
+
+        assert!(signed_area(TRIANGLE.map(|vertex| vertex.position)) > 0.0);
+        for triangle in QUAD_INDICES.chunks(3) {
+            let positions = [0, 1, 2].map(|idx| QUAD[triangle[idx] as usize].position);
+            assert!(signed_area(positions) > 0.0);
+        }
+    }
+
+    #[test]
+    fn rasterizer_presets() {
+        use gfx_hal::pso::{Face, FrontFace, PolygonMode};
+
+        assert_eq!(RasterizerConfig::NO_CULLING.cull_face, Face::NONE);
+        assert_eq!(
+            RasterizerConfig::NO_CULLING.front_face,
+            FrontFace::CounterClockwise
+        );
+        assert_eq!(RasterizerConfig::NO_CULLING.polygon_mode, PolygonMode::Fill);
+
+        // Shadow casters are pushed away from the light whilst decals are pulled forward, both
+        // without a clamp so they don't need `Features::DEPTH_BIAS_CLAMP`.
+        assert_eq!(
+            RasterizerConfig::SHADOW_DEPTH_BIAS,
+            gfx_hal::pso::DepthBias {
+                const_factor: 1.25,
+                clamp: 0.0,
+                slope_factor: 1.75,
+            }
+        );
+        assert_eq!(
+            RasterizerConfig::DECAL_DEPTH_BIAS,
+            gfx_hal::pso::DepthBias {
+                const_factor: -1.0,
+                clamp: 0.0,
+                slope_factor: -1.0,
+            }
+        );
+    }
+
+    #[test]
+    fn unsupported_rasterizer_state_is_removed() {
+        use gfx_hal::pso::{DepthBias, PolygonMode};
+        use gfx_hal::Features;
+
+        let rasterizer = RasterizerConfig {
+            depth_bias: Some(DepthBias {
+                clamp: 0.5,
+                ..RasterizerConfig::SHADOW_DEPTH_BIAS
+            }),
+            polygon_mode: PolygonMode::Line,
+            ..RasterizerConfig::default()
+        };
+        assert_eq!(
+            rasterizer.required_features(),
+            Features::DEPTH_BIAS_CLAMP | Features::NON_FILL_POLYGON_MODE
+        );
+
+        let restricted = rasterizer.restrict_to(Features::empty());
+        assert_eq!(restricted.polygon_mode, PolygonMode::Fill);
+        assert_eq!(restricted.depth_bias.map(|bias| bias.clamp), Some(0.0));
+        assert!(restricted.required_features().is_empty());
+
+        let supported = rasterizer.restrict_to(rasterizer.required_features());
+        assert_eq!(supported.polygon_mode, PolygonMode::Line);
+        assert_eq!(supported.depth_bias, rasterizer.depth_bias);
+    }
+
+    const VALID_VERTEX_SHADER: &str = "#version 450\nvoid main() { gl_Position = vec4(0.0); }\n";
+
+    // An empty directory unique to the test, removed again by the test itself.
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("openglrust-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_spirv(path: &std::path::Path, spirv: &[u32]) {
+        let bytes: Vec<u8> = spirv.iter().flat_map(|word| word.to_le_bytes()).collect();
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn missing_shader_is_not_found() {
+        let dir = temp_dir("read-shader-missing");
+        let assets = AssetResolver::new(vec![dir.clone()]);
+
+        let error = read_shader(&assets, "missing.glsl", None).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+
+        std::fs::write(dir.join("shader.glsl"), "void main() {}").unwrap();
+        let error = read_shader(&assets, "shader.glsl", Some("missing.glsl")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn shader_is_preceded_by_prelude() {
+        let dir = temp_dir("read-shader-prelude");
+        let assets = AssetResolver::new(vec![dir.clone()]);
+        std::fs::write(dir.join("prelude.glsl"), "#version 450").unwrap();
+        std::fs::write(dir.join("shader.glsl"), "void main() {}").unwrap();
+
+        assert_eq!(
+            read_shader(&assets, "shader.glsl", None).unwrap(),
+            "void main() {}"
+        );
+        assert_eq!(
+            read_shader(&assets, "shader.glsl", Some("prelude.glsl")).unwrap(),
+            "#version 450\nvoid main() {}"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn shader_includes_are_expanded_relative_to_the_includer() {
+        let dir = temp_dir("read-shader-include");
+        let assets = AssetResolver::new(vec![dir.clone()]);
+        std::fs::create_dir_all(dir.join("shaders/lib")).unwrap();
+        std::fs::write(dir.join("shaders/lib/luma.glsl"), "float luma;").unwrap();
+        std::fs::write(
+            dir.join("shaders/lib/filter.glsl"),
+            "#include \"luma.glsl\"\nvoid filter() {}",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("shaders/shader.glsl"),
+            "  #include \"lib/filter.glsl\"\nvoid main() {}\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_shader(&assets, "shaders/shader.glsl", None).unwrap(),
+            "float luma;\nvoid filter() {}\nvoid main() {}\n"
+        );
+        assert_eq!(
+            shader_files(&assets, "shaders/shader.glsl"),
+            [
+                Path::new("shaders/shader.glsl"),
+                Path::new("shaders/lib/filter.glsl"),
+                Path::new("shaders/lib/luma.glsl"),
+            ]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_shader_includes_are_rejected() {
+        let dir = temp_dir("read-shader-include-invalid");
+        let assets = AssetResolver::new(vec![dir.clone()]);
+        std::fs::write(dir.join("a.glsl"), "#include \"b.glsl\"").unwrap();
+        std::fs::write(dir.join("b.glsl"), "#include \"a.glsl\"").unwrap();
+        std::fs::write(dir.join("unquoted.glsl"), "#include b.glsl").unwrap();
+
+        let error = read_shader(&assets, "a.glsl", None).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = read_shader(&assets, "unquoted.glsl", None).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cached_shader_is_loaded_instead_of_compiled() {
+        let dir = temp_dir("shader-cache-hit");
+        let compile = || {
+            compile_shader_cached(
+                VALID_VERTEX_SHADER,
+                "valid.glsl",
+                ShaderKind::Vertex,
+                DEFAULT_ENTRY_POINT,
+                Some(&dir),
+            )
+            .unwrap()
+        };
+
+        let compiled = compile();
+        assert_eq!(compiled.first(), Some(&SPIRV_MAGIC));
+        assert_eq!(compile(), compiled);
+
+        // Only a cache hit can return words shaderc would never produce for this source.
+        let path = shader_cache_path(
+            &dir,
+            VALID_VERTEX_SHADER,
+            ShaderKind::Vertex,
+            DEFAULT_ENTRY_POINT,
+        );
+        write_spirv(&path, &[SPIRV_MAGIC, 1, 2, 3]);
+        assert_eq!(compile(), [SPIRV_MAGIC, 1, 2, 3]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cache_key_changes_with_source_and_prelude() {
+        let dir = std::path::Path::new("cache");
+        let path =
+            |shader: &str| shader_cache_path(dir, shader, ShaderKind::Vertex, DEFAULT_ENTRY_POINT);
+
+        let original = path("void main() {}");
+        assert_eq!(path("void main() {}"), original);
+        assert_ne!(path("void main() { }"), original);
+        // `read_shader` prepends the prelude to the source, so changing it changes the key too.
+        assert_ne!(path("#define A\nvoid main() {}"), original);
+        assert_ne!(
+            path("#define B\nvoid main() {}"),
+            path("#define A\nvoid main() {}")
+        );
+        assert_ne!(
+            shader_cache_path(
+                dir,
+                "void main() {}",
+                ShaderKind::Fragment,
+                DEFAULT_ENTRY_POINT
+            ),
+            original
+        );
+    }
+
+    #[test]
+    fn corrupt_cache_entry_is_recompiled() {
+        let dir = temp_dir("shader-cache-corrupt");
+        let path = shader_cache_path(
+            &dir,
+            VALID_VERTEX_SHADER,
+            ShaderKind::Vertex,
+            DEFAULT_ENTRY_POINT,
+        );
+        write_spirv(&path, &[0xdead_beef, 1, 2, 3]);
+
+        let spirv = compile_shader_cached(
+            VALID_VERTEX_SHADER,
+            "valid.glsl",
+            ShaderKind::Vertex,
+            DEFAULT_ENTRY_POINT,
+            Some(&dir),
+        )
+        .unwrap();
+        let expected = compile_shader(
+            VALID_VERTEX_SHADER,
+            "valid.glsl",
+            ShaderKind::Vertex,
+            DEFAULT_ENTRY_POINT,
+        )
+        .unwrap();
+
+        assert_eq!(spirv, expected);
+        assert_eq!(std::fs::read(&path).unwrap().len(), expected.len() * 4);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn candidate(name: &str, can_render: bool) -> AdapterCandidate<'_> {
+        AdapterCandidate {
+            name,
+            device_type: gfx_hal::adapter::DeviceType::DiscreteGpu,
+            can_render,
+        }
+    }
+
+    #[test]
+    fn adapter_without_queue_family_is_rejected() {
+        let candidates = [candidate("Integrated", false), candidate("Discrete", false)];
+
+        assert!(matches!(
+            select_candidate(&candidates, PowerPreference::HighPerformance, None),
+            Err(RenderError::NoQueueFamily)
+        ));
+        assert!(matches!(
+            select_candidate(
+                &candidates,
+                PowerPreference::HighPerformance,
+                Some("discrete")
+            ),
+            Err(RenderError::NoQueueFamily)
+        ));
+    }
+
+    #[test]
+    fn adapter_is_selected_by_name_and_queue_family() {
+        let candidates = [
+            candidate("Discrete A", false),
+            candidate("Discrete B", true),
+            candidate("Other", true),
+        ];
+
+        assert_eq!(
+            select_candidate(
+                &candidates,
+                PowerPreference::HighPerformance,
+                Some("discrete")
+            )
+            .unwrap(),
+            1
+        );
+        assert!(matches!(
+            select_candidate(&candidates, PowerPreference::HighPerformance, Some("missing")),
+            Err(RenderError::AdapterNotFound(name)) if name == "missing"
+        ));
+        assert!(matches!(
+            select_candidate(&[], PowerPreference::HighPerformance, None),
+            Err(RenderError::NoAdapter)
+        ));
+    }
+
+    #[test]
+    fn cache_entry_is_renamed_into_place() {
+        let dir = temp_dir("shader-cache-rename");
+
+        let spirv = compile_shader_cached(
+            VALID_VERTEX_SHADER,
+            "valid.glsl",
+            ShaderKind::Vertex,
+            DEFAULT_ENTRY_POINT,
+            Some(&dir),
+        )
+        .unwrap();
+
+        let entries: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        let path = shader_cache_path(
+            &dir,
+            VALID_VERTEX_SHADER,
+            ShaderKind::Vertex,
+            DEFAULT_ENTRY_POINT,
+        );
+
+        assert_eq!(entries, std::slice::from_ref(&path));
+        assert_eq!(std::fs::read(&path).unwrap().len(), spirv.len() * 4);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
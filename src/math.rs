@@ -0,0 +1,143 @@
+/// Column-major 4x4 matrix, laid out the same way as a GLSL `mat4`.
+pub type Mat4 = [[f32; 4]; 4];
+
+pub const IDENTITY: Mat4 = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// Returns `a * b`, so `b` is applied first when transforming a vector.
+pub fn mul(a: &Mat4, b: &Mat4) -> Mat4 {
+    let mut out = [[0.0; 4]; 4];
+
+    for (col, out_col) in out.iter_mut().enumerate() {
+        for (row, value) in out_col.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b[col][k]).sum();
+        }
+    }
+
+    out
+}
+
+/// The matrix reinterpreted as push constants, matching a GLSL `mat4` member.
+pub fn push_constants(matrix: &Mat4) -> [u32; 16] {
+    let mut out = [0; 16];
+
+    for (value, out) in matrix.iter().flatten().zip(out.iter_mut()) {
+        *out = value.to_bits();
+    }
+
+    out
+}
+
+/// Unit quaternion stored as `[x, y, z, w]`.
+pub type Quat = [f32; 4];
+
+pub const QUAT_IDENTITY: Quat = [0.0, 0.0, 0.0, 1.0];
+
+/// Rotation of `angle` radians around the normalized `axis`.
+pub fn quat_from_axis_angle(axis: [f32; 3], angle: f32) -> Quat {
+    let (sin, cos) = (angle * 0.5).sin_cos();
+
+    [axis[0] * sin, axis[1] * sin, axis[2] * sin, cos]
+}
+
+/// Perspective projection for Vulkan's clip space, where y points down and depth goes from 0 at
+/// `near` to 1 at `far`. The view space looks down `-z`, with `fov_y` in radians.
+pub fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Mat4 {
+    let focal_length = 1.0 / (fov_y * 0.5).tan();
+
+    [
+        [focal_length / aspect, 0.0, 0.0, 0.0],
+        [0.0, -focal_length, 0.0, 0.0],
+        [0.0, 0.0, far / (near - far), -1.0],
+        [0.0, 0.0, near * far / (near - far), 0.0],
+    ]
+}
+
+/// View matrix of a camera at `eye` looking at `target`, with `up` pointing roughly upwards.
+pub fn look_at(eye: [f32; 3], target: [f32; 3], up: [f32; 3]) -> Mat4 {
+    let forward = normalize(sub(target, eye));
+    let side = normalize(cross(forward, up));
+    let up = cross(side, forward);
+
+    [
+        [side[0], up[0], -forward[0], 0.0],
+        [side[1], up[1], -forward[1], 0.0],
+        [side[2], up[2], -forward[2], 0.0],
+        [-dot(side, eye), -dot(up, eye), dot(forward, eye), 1.0],
+    ]
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(a: [f32; 3]) -> [f32; 3] {
+    let length = dot(a, a).sqrt();
+
+    [a[0] / length, a[1] / length, a[2] / length]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(matrix: &Mat4, point: [f32; 3]) -> [f32; 3] {
+        let clip: Vec<f32> = (0..4)
+            .map(|row| {
+                (0..3).map(|col| matrix[col][row] * point[col]).sum::<f32>() + matrix[3][row]
+            })
+            .collect();
+
+        [clip[0] / clip[3], clip[1] / clip[3], clip[2] / clip[3]]
+    }
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        assert!(
+            actual
+                .iter()
+                .zip(expected)
+                .all(|(actual, expected)| (actual - expected).abs() < 1e-5),
+            "Expected {:?}, got {:?}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn perspective_maps_near_and_far_to_depth_range() {
+        let projection = perspective(std::f32::consts::FRAC_PI_2, 2.0, 0.5, 10.0);
+
+        assert_close(transform(&projection, [0.0, 0.0, -0.5]), [0.0, 0.0, 0.0]);
+        assert_close(transform(&projection, [0.0, 0.0, -10.0]), [0.0, 0.0, 1.0]);
+        // Up in view space is up on screen, which is towards -y in clip space.
+        assert_close(
+            transform(&projection, [2.0, 1.0, -1.0]),
+            [1.0, -1.0, 10.0 / 19.0],
+        );
+    }
+
+    #[test]
+    fn look_at_moves_target_in_front_of_camera() {
+        let view = look_at([0.0, 3.0, 4.0], [0.0; 3], [0.0, 1.0, 0.0]);
+
+        assert_close(transform(&view, [0.0; 3]), [0.0, 0.0, -5.0]);
+        assert_close(transform(&view, [0.0, 3.0, 4.0]), [0.0; 3]);
+        assert_close(transform(&view, [1.0, 0.0, 0.0]), [1.0, 0.0, -5.0]);
+    }
+}
@@ -0,0 +1,1878 @@
+use crate::assets::{AssetResolver, FileWatcher};
+use crate::attachment::{self, AttachmentImage};
+use crate::buffer::{Buffer, DrawIndexedCommand, Mesh, MeshBatch};
+use crate::command_log::{CommandLogMode, CommandRecorder, FrameLog};
+use crate::math;
+use crate::scene::SceneGraph;
+use crate::texture::{self, Texture};
+use crate::vertex::{Vertex, VertexLayout};
+use crate::{
+    generate_pipeline, present_mode, print_validation_message, read_shader, select_adapter,
+    set_validation_callback, shader_files, swapchain_usage, AntiAlias, ColorAdjustment, GridConfig,
+    RasterizerConfig, RenderConfig, RenderError, ShaderSource, StderrLogger, DEFAULT_ENTRY_POINT,
+    QUAD, QUAD_INDICES, SHADER_OPTIONS, TRANSFORM_PUSH_CONSTANTS_SIZE, TRIANGLE, TRIANGLE_INDICES,
+};
+
+use gfx_hal::device::Device;
+use gfx_hal::window::{Extent2D, PresentationSurface, Surface};
+use gfx_hal::Instance;
+use std::mem::ManuallyDrop;
+
+pub struct GpuResources<B: gfx_hal::Backend> {
+    instance: B::Instance,
+    // `None` for headless renderers.
+    surface: Option<B::Surface>,
+    device: B::Device,
+    render_passes: Vec<B::RenderPass>,
+    pipeline_layouts: Vec<B::PipelineLayout>,
+    pipelines: Vec<B::GraphicsPipeline>,
+    meshes: Vec<Mesh<B>>,
+    // The same meshes packed together, only uploaded for indirect draws.
+    mesh_batch: Option<MeshBatch<B>>,
+    // Recreated along with the swapchain.
+    depth_image: Option<AttachmentImage<B>>,
+    // Rendered to instead of the swapchain image when MSAA is enabled, recreated along with it.
+    msaa_image: Option<AttachmentImage<B>>,
+    // One of each per frame in flight.
+    command_pools: Vec<B::CommandPool>,
+    submission_fences: Vec<B::Fence>,
+    rendering_semaphores: Vec<B::Semaphore>,
+    descriptor_set_layouts: Vec<B::DescriptorSetLayout>,
+    // Only created along with a texture, freeing it frees the texture's descriptor set.
+    descriptor_pool: Option<B::DescriptorPool>,
+    texture: Option<(Texture<B>, B::DescriptorSet)>,
+    // Only created with FXAA.
+    fxaa: Option<FxaaTarget<B>>,
+}
+
+// Required because drop requires &mut self whilst destroy..() in gfx_hal takes exclusive ownership
+// of the object through self.
+struct ResourceHolder<B: gfx_hal::Backend>(ManuallyDrop<GpuResources<B>>);
+
+impl<B: gfx_hal::Backend> GpuResources<B> {
+    // Only called once render_frame made sure the renderer isn't headless.
+    fn surface(&mut self) -> &mut B::Surface {
+        self.surface.as_mut().expect("Renderer has no surface")
+    }
+
+    // Framebuffers of the scene's render pass and, with FXAA, of the FXAA pass. Either way the
+    // frame ends up in `target`, with FXAA the scene is rendered into the prepared FXAA image.
+    unsafe fn create_framebuffers(
+        &self,
+        target: &B::ImageView,
+        depth: &AttachmentImage<B>,
+        msaa: Option<&AttachmentImage<B>>,
+        extent: Extent2D,
+    ) -> Result<(B::Framebuffer, Option<B::Framebuffer>), RenderError> {
+        let fxaa_image = self.fxaa.as_ref().map(FxaaTarget::image);
+        let scene_target = fxaa_image.map_or(target, |image| &image.view);
+
+        let framebuffer = create_framebuffer(
+            &self.device,
+            &self.render_passes[0],
+            scene_target,
+            depth,
+            msaa,
+            extent,
+        )?;
+        if fxaa_image.is_none() {
+            return Ok((framebuffer, None));
+        }
+
+        let fxaa_framebuffer = self.device.create_framebuffer(
+            &self.render_passes[1],
+            std::iter::once(target),
+            gfx_hal::image::Extent {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+        );
+
+        match fxaa_framebuffer {
+            Ok(fxaa_framebuffer) => Ok((framebuffer, Some(fxaa_framebuffer))),
+            Err(error) => {
+                self.device.destroy_framebuffer(framebuffer);
+                Err(error.into())
+            }
+        }
+    }
+}
+
+/// Size in bytes of the texel size pushed to the FXAA fragment shader.
+const FXAA_PUSH_CONSTANTS_SIZE: u32 = 2 * 4;
+
+// Offscreen image the scene is rendered into with FXAA, along with the descriptor set the FXAA
+// pass samples it through. Shared by the frames in flight, like the depth image.
+struct FxaaTarget<B: gfx_hal::Backend> {
+    sampler: B::Sampler,
+    // Only holds the descriptor set, freeing it frees the set.
+    descriptor_pool: B::DescriptorPool,
+    descriptor_set: B::DescriptorSet,
+    // Created for the first frame, recreated whenever the extent or format of the frames changes.
+    image: Option<(AttachmentImage<B>, Extent2D, gfx_hal::format::Format)>,
+}
+
+impl<B: gfx_hal::Backend> FxaaTarget<B> {
+    // `set_layout` must hold a single combined image sampler, like the texture's layout.
+    unsafe fn new(
+        device: &B::Device,
+        set_layout: &B::DescriptorSetLayout,
+    ) -> Result<Self, RenderError> {
+        use gfx_hal::image::{Filter, SamplerDesc, WrapMode};
+        use gfx_hal::pso::{
+            DescriptorPool, DescriptorPoolCreateFlags, DescriptorRangeDesc, DescriptorType,
+            ImageDescriptorType,
+        };
+
+        // FXAA samples between texels, relying on the filtering to blend them.
+        let sampler = device.create_sampler(&SamplerDesc::new(Filter::Linear, WrapMode::Clamp))?;
+
+        let descriptor_pool = device.create_descriptor_pool(
+            1,
+            [DescriptorRangeDesc {
+                ty: DescriptorType::Image {
+                    ty: ImageDescriptorType::Sampled { with_sampler: true },
+                },
+                count: 1,
+            }],
+            DescriptorPoolCreateFlags::empty(),
+        );
+        let mut descriptor_pool = match descriptor_pool {
+            Ok(descriptor_pool) => descriptor_pool,
+            Err(error) => {
+                device.destroy_sampler(sampler);
+                return Err(error.into());
+            }
+        };
+
+        match descriptor_pool.allocate_set(set_layout) {
+            Ok(descriptor_set) => Ok(Self {
+                sampler,
+                descriptor_pool,
+                descriptor_set,
+                image: None,
+            }),
+            Err(error) => {
+                device.destroy_descriptor_pool(descriptor_pool);
+                device.destroy_sampler(sampler);
+                Err(error.into())
+            }
+        }
+    }
+
+    // Makes sure the image matches the frames about to be rendered.
+    unsafe fn prepare(
+        &mut self,
+        device: &B::Device,
+        memory_types: &[gfx_hal::adapter::MemoryType],
+        extent: Extent2D,
+        format: gfx_hal::format::Format,
+    ) -> Result<(), RenderError> {
+        use gfx_hal::image::{Layout, Usage};
+        use gfx_hal::pso::{Descriptor, DescriptorSetWrite};
+
+        if let Some((_, image_extent, image_format)) = &self.image {
+            if *image_extent == extent && *image_format == format {
+                return Ok(());
+            }
+        }
+
+        // Frames in flight may still be sampling the old image through the descriptor set.
+        device.wait_idle()?;
+
+        if let Some((image, _, _)) = self.image.take() {
+            image.destroy(device);
+        }
+
+        let image = AttachmentImage::new(
+            device,
+            memory_types,
+            extent,
+            format,
+            Usage::COLOR_ATTACHMENT | Usage::SAMPLED,
+            1,
+        )?;
+
+        device.write_descriptor_sets(std::iter::once(DescriptorSetWrite {
+            set: &self.descriptor_set,
+            binding: 0,
+            array_offset: 0,
+            descriptors: std::iter::once(Descriptor::CombinedImageSampler(
+                &image.view,
+                Layout::ShaderReadOnlyOptimal,
+                &self.sampler,
+            )),
+        }));
+
+        self.image = Some((image, extent, format));
+
+        Ok(())
+    }
+
+    // Only called after `prepare`.
+    fn image(&self) -> &AttachmentImage<B> {
+        let (image, _, _) = self.image.as_ref().expect("FXAA image wasn't prepared");
+        image
+    }
+
+    unsafe fn destroy(self, device: &B::Device) {
+        if let Some((image, _, _)) = self.image {
+            image.destroy(device);
+        }
+        device.destroy_descriptor_pool(self.descriptor_pool);
+        device.destroy_sampler(self.sampler);
+    }
+}
+
+impl<B: gfx_hal::Backend> Drop for ResourceHolder<B> {
+    fn drop(&mut self) {
+        unsafe {
+            use gfx_hal::window::PresentationSurface;
+            use gfx_hal::Instance;
+
+            let GpuResources {
+                instance,
+                surface,
+                device,
+                render_passes,
+                pipeline_layouts,
+                pipelines,
+                meshes,
+                mesh_batch,
+                depth_image,
+                msaa_image,
+                command_pools,
+                submission_fences,
+                rendering_semaphores,
+                descriptor_set_layouts,
+                descriptor_pool,
+                texture,
+                fxaa,
+            } = ManuallyDrop::take(&mut self.0);
+
+            // Nothing can be destroyed whilst frames are still in flight.
+            let _ = device.wait_idle();
+
+            if let Some(depth_image) = depth_image {
+                depth_image.destroy(&device);
+            }
+            if let Some(msaa_image) = msaa_image {
+                msaa_image.destroy(&device);
+            }
+            for mesh in meshes {
+                mesh.destroy(&device);
+            }
+            if let Some(mesh_batch) = mesh_batch {
+                mesh_batch.destroy(&device);
+            }
+            if let Some((texture, _)) = texture {
+                texture.destroy(&device);
+            }
+            if let Some(descriptor_pool) = descriptor_pool {
+                device.destroy_descriptor_pool(descriptor_pool);
+            }
+            if let Some(fxaa) = fxaa {
+                fxaa.destroy(&device);
+            }
+            for rendering_semaphore in rendering_semaphores {
+                device.destroy_semaphore(rendering_semaphore);
+            }
+            for submission_fence in submission_fences {
+                device.destroy_fence(submission_fence);
+            }
+            for pipeline in pipelines {
+                device.destroy_graphics_pipeline(pipeline);
+            }
+            for pipeline_layout in pipeline_layouts {
+                device.destroy_pipeline_layout(pipeline_layout);
+            }
+            for descriptor_set_layout in descriptor_set_layouts {
+                device.destroy_descriptor_set_layout(descriptor_set_layout);
+            }
+            for render_pass in render_passes {
+                device.destroy_render_pass(render_pass);
+            }
+            for command_pool in command_pools {
+                device.destroy_command_pool(command_pool);
+            }
+            if let Some(mut surface) = surface {
+                surface.unconfigure_swapchain(&device);
+                instance.destroy_surface(surface);
+            }
+        }
+    }
+}
+
+/// Shaders and fixed function state of one of the built-in pipelines, kept around so the
+/// pipeline can be rebuilt when its shaders change.
+struct PipelineDesc {
+    // Index of the pipeline layout, shared by variants of the same pipeline.
+    layout: usize,
+    // Index of the render pass drawn in, only the scene's first pass is multisampled.
+    render_pass: usize,
+    vertex_shader: &'static str,
+    fragment_shader: &'static str,
+    vertex_layout: VertexLayout,
+    rasterizer: RasterizerConfig,
+    depth_test: Option<gfx_hal::pso::DepthTest>,
+}
+
+impl PipelineDesc {
+    unsafe fn build<B: gfx_hal::Backend>(
+        &self,
+        device: &B::Device,
+        render_passes: &[B::RenderPass],
+        pipeline_layout: &B::PipelineLayout,
+        assets: &AssetResolver,
+        samples: gfx_hal::image::NumSamples,
+        shader_cache: Option<&std::path::Path>,
+    ) -> Result<B::GraphicsPipeline, RenderError> {
+        let samples = if self.render_pass == 0 { samples } else { 1 };
+
+        generate_pipeline::<B>(
+            device,
+            &render_passes[self.render_pass],
+            pipeline_layout,
+            ShaderSource {
+                name: self.vertex_shader,
+                source: &read_shader(assets, self.vertex_shader, Some(SHADER_OPTIONS))?,
+            },
+            ShaderSource {
+                name: self.fragment_shader,
+                source: &read_shader(assets, self.fragment_shader, Some(SHADER_OPTIONS))?,
+            },
+            DEFAULT_ENTRY_POINT,
+            &self.vertex_layout,
+            self.rasterizer,
+            self.depth_test,
+            samples,
+            shader_cache,
+        )
+    }
+
+    // Watches both stages along with the options prepended to them and everything they include.
+    fn watch(&self, assets: &AssetResolver) -> FileWatcher {
+        FileWatcher::new(
+            [SHADER_OPTIONS, self.vertex_shader, self.fragment_shader]
+                .iter()
+                .flat_map(|name| shader_files(assets, name))
+                .filter_map(|name| assets.resolve(name).ok()),
+        )
+    }
+}
+
+/// Owns the GPU state required to render into a window, independent of the event loop driving
+/// it.
+pub struct Renderer<B: gfx_hal::Backend> {
+    config: RenderConfig,
+    resources: ResourceHolder<B>,
+    adapter: gfx_hal::adapter::Adapter<B>,
+    queue_group: gfx_hal::queue::QueueGroup<B>,
+    // One per frame in flight, allocated from the matching command pool.
+    command_buffers: Vec<B::CommandBuffer>,
+    surface_color_format: gfx_hal::format::Format,
+    // Layout the color attachment is left in by the render pass.
+    color_final_layout: gfx_hal::image::Layout,
+    surface_extent: Extent2D,
+    // Index of the frame in flight that the next frame records.
+    next_frame: usize,
+    frames_in_flight: usize,
+    frame_log: Option<FrameLog>,
+    // World matrices of the scene's leaves, each drawn as a separate object.
+    object_matrices: Vec<math::Mat4>,
+    // Indexed the same as the pipelines.
+    pipeline_descs: Vec<PipelineDesc>,
+    // Empty unless hot reloading is enabled.
+    shader_watchers: Vec<FileWatcher>,
+    // The swapchain is a chain of images to render onto.
+    configure_swapchain: bool,
+}
+
+impl<B: gfx_hal::Backend> Renderer<B> {
+    /// Creates the device, pipelines and everything else needed to render into `window`. The
+    /// swapchain itself is only configured once the first frame is rendered.
+    pub fn new(window: &winit::window::Window, config: RenderConfig) -> Result<Self, RenderError> {
+        Self::create(Some(window), config)
+    }
+
+    /// Creates a renderer without a window, which can only render through
+    /// [`Renderer::render_to_image`].
+    pub fn headless(config: RenderConfig) -> Result<Self, RenderError> {
+        Self::create(None, config)
+    }
+
+    fn create(
+        window: Option<&winit::window::Window>,
+        mut config: RenderConfig,
+    ) -> Result<Self, RenderError> {
+        match config.validation_callback.take() {
+            Some(callback) => {
+                set_validation_callback(callback, Some(Box::new(StderrLogger::from_env())))?
+            }
+            // An application that installed its own logger already receives the messages.
+            None if config.enable_validation => {
+                if let Err(error) = set_validation_callback(
+                    Box::new(print_validation_message),
+                    Some(Box::new(StderrLogger::from_env())),
+                ) {
+                    log::info!(
+                        "Validation messages are left to the installed logger: {}",
+                        error
+                    );
+                }
+            }
+            None => (),
+        }
+
+        // Logged after the validation callback is installed, so it reaches its fallback logger.
+        if config.enable_validation && !cfg!(debug_assertions) {
+            log::warn!("Validation layers are only available in debug builds");
+        }
+
+        let instance = B::Instance::create(env!("CARGO_PKG_NAME"), 1)?;
+        let surface = match window {
+            Some(window) => Some(unsafe { instance.create_surface(window)? }),
+            None => None,
+        };
+        let adapter = select_adapter(
+            instance.enumerate_adapters(),
+            config.power_preference,
+            surface.as_ref(),
+            config.adapter_name.as_deref(),
+        )?;
+
+        log::info!("Using adapter {:?}", adapter.info);
+
+        let samples = config.anti_alias.samples();
+        {
+            use gfx_hal::adapter::PhysicalDevice;
+
+            let limits = adapter.physical_device.limits();
+            let supported =
+                limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+
+            if !samples.is_power_of_two() || supported & samples == 0 {
+                return Err(RenderError::UnsupportedSampleCount(samples));
+            }
+        }
+        let fxaa = config.anti_alias == AntiAlias::Fxaa;
+
+        let (rasterizer, wireframe_rasterizer) = {
+            use gfx_hal::adapter::PhysicalDevice;
+
+            let features = adapter.physical_device.features();
+            let rasterizer = config.rasterizer.restrict_to(features);
+            let wireframe = RasterizerConfig {
+                polygon_mode: gfx_hal::pso::PolygonMode::Line,
+                ..rasterizer
+            };
+
+            (rasterizer, wireframe.restrict_to(features))
+        };
+
+        let (device, mut queue_group) = {
+            use gfx_hal::queue::QueueFamily;
+
+            let queue_family = adapter
+                .queue_families
+                .iter()
+                .find(|family| {
+                    family.queue_type().supports_graphics()
+                        && surface
+                            .as_ref()
+                            .is_none_or(|surface| surface.supports_queue_family(family))
+                })
+                .ok_or(RenderError::NoQueueFamily)?;
+            let mut gpu = unsafe {
+                use gfx_hal::adapter::PhysicalDevice;
+                adapter.physical_device.open(
+                    &[(queue_family, &[1.0])],
+                    rasterizer.required_features() | wireframe_rasterizer.required_features(),
+                )?
+            };
+
+            (gpu.device, gpu.queue_groups.pop().unwrap())
+        };
+
+        // Upper bound of frames the CPU may record ahead of the GPU, the actual count is further
+        // limited by the number of swapchain images.
+        let max_frames_in_flight = config.max_frames_in_flight.max(1);
+
+        // command buffer => a structure of commands to render anything to the GPU, this is done
+        //                   via a command buffer. These command buffers are allocated from a
+        //                   command pool.
+        //
+        // Each frame in flight gets its own pool, so resetting one doesn't affect the command
+        // buffers of frames that are still executing.
+        let (mut command_pools, command_buffers): (Vec<_>, Vec<_>) = (0..max_frames_in_flight)
+            .map(|_| unsafe {
+                use gfx_hal::command::Level;
+                use gfx_hal::pool::{CommandPool, CommandPoolCreateFlags};
+
+                let mut command_pool = device
+                    .create_command_pool(queue_group.family, CommandPoolCreateFlags::empty())?;
+                let command_buffer = command_pool.allocate_one(Level::Primary);
+
+                Ok((command_pool, command_buffer))
+            })
+            .collect::<Result<Vec<_>, RenderError>>()?
+            .into_iter()
+            .unzip();
+
+        let surface_color_format = {
+            use gfx_hal::format::{ChannelType, Format};
+
+            let supported_formats = match &surface {
+                Some(surface) => surface
+                    .supported_formats(&adapter.physical_device)
+                    .unwrap_or(vec![]),
+                None => vec![HEADLESS_FORMAT],
+            };
+
+            let default_format = *supported_formats.first().unwrap_or(&Format::Rgba8Srgb);
+
+            supported_formats
+                .into_iter()
+                .find(|format| format.base_format().1 == ChannelType::Srgb)
+                .unwrap_or(default_format)
+        };
+
+        // Headless renders are copied out of the color attachment rather than presented.
+        let color_final_layout = match surface {
+            Some(_) => gfx_hal::image::Layout::Present,
+            None => gfx_hal::image::Layout::TransferSrcOptimal,
+        };
+
+        let render_passes = unsafe {
+            create_render_passes::<B>(
+                &device,
+                surface_color_format,
+                samples,
+                color_final_layout,
+                fxaa,
+            )?
+        };
+
+        // This defines textures and matrices required by the shaders, the transform is pushed to
+        // the vertex stage with the color adjustment right after it for the fragment stage.
+        let pipeline_layout = unsafe {
+            use gfx_hal::pso::ShaderStageFlags;
+
+            device.create_pipeline_layout(
+                &[],
+                &[
+                    (ShaderStageFlags::VERTEX, 0..TRANSFORM_PUSH_CONSTANTS_SIZE),
+                    (
+                        ShaderStageFlags::FRAGMENT,
+                        TRANSFORM_PUSH_CONSTANTS_SIZE
+                            ..TRANSFORM_PUSH_CONSTANTS_SIZE + ColorAdjustment::PUSH_CONSTANTS_SIZE,
+                    ),
+                ],
+            )?
+        };
+
+        let grid_pipeline_layout = unsafe {
+            use gfx_hal::pso::ShaderStageFlags;
+
+            device.create_pipeline_layout(
+                &[],
+                &[
+                    (ShaderStageFlags::VERTEX, 0..TRANSFORM_PUSH_CONSTANTS_SIZE),
+                    (
+                        ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT,
+                        TRANSFORM_PUSH_CONSTANTS_SIZE
+                            ..TRANSFORM_PUSH_CONSTANTS_SIZE + GridConfig::PUSH_CONSTANTS_SIZE,
+                    ),
+                ],
+            )?
+        };
+
+        // A single combined image sampler, read by the textured fragment shader.
+        let texture_set_layout = unsafe {
+            use gfx_hal::pso::{
+                DescriptorSetLayoutBinding, DescriptorType, ImageDescriptorType, ShaderStageFlags,
+            };
+
+            device.create_descriptor_set_layout(
+                &[DescriptorSetLayoutBinding {
+                    binding: 0,
+                    ty: DescriptorType::Image {
+                        ty: ImageDescriptorType::Sampled { with_sampler: true },
+                    },
+                    count: 1,
+                    stage_flags: ShaderStageFlags::FRAGMENT,
+                    immutable_samplers: false,
+                }],
+                &[],
+            )?
+        };
+
+        let textured_pipeline_layout = unsafe {
+            use gfx_hal::pso::ShaderStageFlags;
+
+            device.create_pipeline_layout(
+                std::iter::once(&texture_set_layout),
+                &[(ShaderStageFlags::VERTEX, 0..TRANSFORM_PUSH_CONSTANTS_SIZE)],
+            )?
+        };
+
+        let mut pipeline_layouts = vec![
+            pipeline_layout,
+            grid_pipeline_layout,
+            textured_pipeline_layout,
+        ];
+
+        // Samples the scene through the same kind of descriptor set as textures.
+        if fxaa {
+            pipeline_layouts.push(unsafe {
+                use gfx_hal::pso::ShaderStageFlags;
+
+                device.create_pipeline_layout(
+                    std::iter::once(&texture_set_layout),
+                    &[(ShaderStageFlags::FRAGMENT, 0..FXAA_PUSH_CONSTANTS_SIZE)],
+                )?
+            });
+        }
+
+        let main_desc = PipelineDesc {
+            layout: 0,
+            render_pass: 0,
+            vertex_shader: "shaders/vertex.glsl",
+            fragment_shader: "shaders/fragment.glsl",
+            vertex_layout: VertexLayout::empty()
+                .with_buffer::<Vertex>(gfx_hal::pso::VertexInputRate::Vertex),
+            rasterizer,
+            depth_test: Some(gfx_hal::pso::DepthTest {
+                fun: gfx_hal::pso::Comparison::LessEqual,
+                write: true,
+            }),
+        };
+        // Drawn instead of the main pipeline in wireframe mode.
+        let wireframe_desc = PipelineDesc {
+            vertex_layout: main_desc.vertex_layout.clone(),
+            rasterizer: wireframe_rasterizer,
+            ..main_desc
+        };
+        // Drawn instead of the main pipeline when there's a texture.
+        let textured_desc = PipelineDesc {
+            layout: 2,
+            vertex_shader: "shaders/textured_vertex.glsl",
+            fragment_shader: "shaders/textured_fragment.glsl",
+            vertex_layout: main_desc.vertex_layout.clone(),
+            ..main_desc
+        };
+        // Drawn instead of the textured pipeline in wireframe mode.
+        let textured_wireframe_desc = PipelineDesc {
+            vertex_layout: main_desc.vertex_layout.clone(),
+            rasterizer: wireframe_rasterizer,
+            ..textured_desc
+        };
+
+        let mut pipeline_descs = vec![
+            main_desc,
+            PipelineDesc {
+                layout: 1,
+                render_pass: 0,
+                vertex_shader: "shaders/grid_vertex.glsl",
+                fragment_shader: "shaders/grid_fragment.glsl",
+                vertex_layout: VertexLayout::empty(),
+                // Visible from above and below.
+                rasterizer: RasterizerConfig::NO_CULLING,
+                // Hidden by the scene, without hiding anything drawn after it.
+                depth_test: Some(gfx_hal::pso::DepthTest {
+                    fun: gfx_hal::pso::Comparison::LessEqual,
+                    write: false,
+                }),
+            },
+            wireframe_desc,
+            textured_desc,
+            textured_wireframe_desc,
+        ];
+
+        if fxaa {
+            pipeline_descs.push(PipelineDesc {
+                layout: 3,
+                render_pass: 1,
+                vertex_shader: "shaders/fxaa_vertex.glsl",
+                fragment_shader: "shaders/fxaa_fragment.glsl",
+                vertex_layout: VertexLayout::empty(),
+                rasterizer: RasterizerConfig::NO_CULLING,
+                depth_test: None,
+            });
+        }
+
+        let pipelines = pipeline_descs
+            .iter()
+            .map(|desc| unsafe {
+                desc.build::<B>(
+                    &device,
+                    &render_passes,
+                    &pipeline_layouts[desc.layout],
+                    &config.assets,
+                    samples,
+                    config.shader_cache.as_deref(),
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let shader_watchers = if config.hot_reload {
+            pipeline_descs
+                .iter()
+                .map(|desc| desc.watch(&config.assets))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let (triangle, quad, mesh_batch) = unsafe {
+            use gfx_hal::adapter::PhysicalDevice;
+
+            let memory_types = adapter.physical_device.memory_properties().memory_types;
+
+            // Same order as the meshes, so each mesh's draw command is at its own index.
+            let mesh_batch = if config.indirect {
+                Some(MeshBatch::<B>::upload(
+                    &device,
+                    &memory_types,
+                    &[
+                        (&TRIANGLE[..], &TRIANGLE_INDICES[..]),
+                        (&QUAD[..], &QUAD_INDICES[..]),
+                    ],
+                )?)
+            } else {
+                None
+            };
+
+            (
+                Mesh::<B>::upload(&device, &memory_types, &TRIANGLE)?,
+                Mesh::<B>::upload_indexed(&device, &memory_types, &QUAD, &QUAD_INDICES)?,
+                mesh_batch,
+            )
+        };
+
+        let (descriptor_pool, texture) = match &config.texture {
+            Some(path) => unsafe {
+                use gfx_hal::adapter::PhysicalDevice;
+                use gfx_hal::image::Layout;
+                use gfx_hal::pso::{
+                    Descriptor, DescriptorPool, DescriptorPoolCreateFlags, DescriptorRangeDesc,
+                    DescriptorSetWrite, DescriptorType, ImageDescriptorType,
+                };
+
+                let memory_types = adapter.physical_device.memory_properties().memory_types;
+                let texture = if texture::is_compressed_texture(path) {
+                    texture::load_compressed_texture(
+                        &adapter.physical_device,
+                        &device,
+                        &memory_types,
+                        &mut command_pools[0],
+                        &mut queue_group.queues[0],
+                        &config.assets,
+                        path,
+                    )?
+                } else {
+                    let bytes = config
+                        .assets
+                        .read(path)
+                        .map_err(image::ImageError::IoError)?;
+                    let pixels = image::load_from_memory(&bytes)?.to_rgba8();
+
+                    Texture::upload(
+                        &device,
+                        &memory_types,
+                        &mut command_pools[0],
+                        &mut queue_group.queues[0],
+                        &pixels,
+                    )?
+                };
+
+                let mut descriptor_pool = device.create_descriptor_pool(
+                    1,
+                    [DescriptorRangeDesc {
+                        ty: DescriptorType::Image {
+                            ty: ImageDescriptorType::Sampled { with_sampler: true },
+                        },
+                        count: 1,
+                    }],
+                    DescriptorPoolCreateFlags::empty(),
+                )?;
+                let descriptor_set = descriptor_pool.allocate_set(&texture_set_layout)?;
+
+                device.write_descriptor_sets(std::iter::once(DescriptorSetWrite {
+                    set: &descriptor_set,
+                    binding: 0,
+                    array_offset: 0,
+                    descriptors: std::iter::once(Descriptor::CombinedImageSampler(
+                        &texture.image.view,
+                        Layout::ShaderReadOnlyOptimal,
+                        &texture.sampler,
+                    )),
+                }));
+
+                (Some(descriptor_pool), Some((texture, descriptor_set)))
+            },
+            None => (None, None),
+        };
+
+        let fxaa = if fxaa {
+            Some(unsafe { FxaaTarget::new(&device, &texture_set_layout)? })
+        } else {
+            None
+        };
+
+        let submission_fences = (0..max_frames_in_flight)
+            .map(|_| device.create_fence(true))
+            .collect::<Result<_, _>>()?;
+        let rendering_semaphores = (0..max_frames_in_flight)
+            .map(|_| device.create_semaphore())
+            .collect::<Result<_, _>>()?;
+
+        let resources = ResourceHolder(ManuallyDrop::new(GpuResources {
+            instance,
+            surface,
+            device,
+            command_pools,
+            render_passes,
+            pipeline_layouts,
+            pipelines,
+            meshes: vec![triangle, quad],
+            mesh_batch,
+            depth_image: None,
+            msaa_image: None,
+            submission_fences,
+            rendering_semaphores,
+            descriptor_set_layouts: vec![texture_set_layout],
+            descriptor_pool,
+            texture,
+            fxaa,
+        }));
+
+        let frame_log = match config.command_log {
+            CommandLogMode::Off => None,
+            _ => Some(FrameLog::default()),
+        };
+
+        // Headless renderers get their size passed to each render instead.
+        let surface_extent = match window.map(winit::window::Window::inner_size) {
+            Some(size) => Extent2D {
+                width: size.width,
+                height: size.height,
+            },
+            None => Extent2D {
+                width: 0,
+                height: 0,
+            },
+        };
+
+        Ok(Self {
+            config,
+            resources,
+            adapter,
+            queue_group,
+            command_buffers,
+            surface_color_format,
+            color_final_layout,
+            surface_extent,
+            next_frame: 0,
+            frames_in_flight: max_frames_in_flight,
+            frame_log,
+            object_matrices: Vec::new(),
+            pipeline_descs,
+            shader_watchers,
+            configure_swapchain: true,
+        })
+    }
+
+    pub fn config(&self) -> &RenderConfig {
+        &self.config
+    }
+
+    pub fn config_mut(&mut self) -> &mut RenderConfig {
+        &mut self.config
+    }
+
+    /// Rebuilds the pipelines whose shaders changed on disk since the last call, when
+    /// [`RenderConfig::hot_reload`] is enabled. Shaders that fail to compile are logged and the
+    /// previous pipeline is kept.
+    pub fn reload_changed_shaders(&mut self) -> Result<(), RenderError> {
+        let resources: &mut GpuResources<_> = &mut self.resources.0;
+
+        for (idx, watcher) in self.shader_watchers.iter_mut().enumerate() {
+            if !watcher.poll() {
+                continue;
+            }
+
+            let desc = &self.pipeline_descs[idx];
+            let pipeline = unsafe {
+                desc.build::<B>(
+                    &resources.device,
+                    &resources.render_passes,
+                    &resources.pipeline_layouts[desc.layout],
+                    &self.config.assets,
+                    self.config.anti_alias.samples(),
+                    self.config.shader_cache.as_deref(),
+                )
+            };
+
+            match pipeline {
+                Ok(pipeline) => unsafe {
+                    // Frames in flight may still be using the old pipeline.
+                    resources.device.wait_idle()?;
+
+                    let old = std::mem::replace(&mut resources.pipelines[idx], pipeline);
+                    resources.device.destroy_graphics_pipeline(old);
+
+                    log::info!(
+                        "Reloaded {} and {}",
+                        desc.vertex_shader,
+                        desc.fragment_shader
+                    );
+                },
+                // Either stage or the options they share may have triggered the reload.
+                Err(error) => log::error!(
+                    "Failed to reload {} and {}: {}",
+                    desc.vertex_shader,
+                    desc.fragment_shader,
+                    error
+                ),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Model-view-projection matrix of the triangle in the following frames.
+    pub fn set_transform(&mut self, matrix: math::Mat4) {
+        self.config.transform = matrix;
+    }
+
+    /// Draws the mesh once for every leaf of `scene` from the next frame on, with the leaf's
+    /// world matrix applied before [`RenderConfig::transform`]. Until a scene with leaves is set
+    /// the mesh is drawn once with the transform alone.
+    pub fn set_scene(&mut self, scene: &SceneGraph) {
+        scene.leaf_world_matrices(&mut self.object_matrices);
+    }
+
+    /// Color used to clear the following frames, in linear RGBA.
+    pub fn set_clear_color(&mut self, rgba: [f32; 4]) {
+        self.config.clear_color = rgba;
+    }
+
+    /// Shows or hides the grid helper from the next frame on.
+    pub fn show_grid(&mut self, visible: bool) {
+        self.config.show_grid = visible;
+    }
+
+    /// Recreates the swapchain at the given size before the next frame is rendered.
+    pub fn resize(&mut self, extent: Extent2D) {
+        self.surface_extent = extent;
+        self.configure_swapchain = true;
+    }
+
+    /// Commands recorded during the first frame, when enabled by [`RenderConfig::command_log`].
+    /// Returns `None` until that frame has been rendered and after the log has been taken.
+    pub fn take_frame_log(&mut self) -> Option<FrameLog> {
+        if self.frame_log.as_ref()?.commands.is_empty() {
+            return None;
+        }
+
+        self.frame_log.take()
+    }
+
+    /// Records, submits and presents a single frame. Returns `false` when the swapchain was out
+    /// of date and the frame had to be skipped, it's reconfigured on the next call.
+    ///
+    /// A [`RenderError::Swapchain`] error leaves the swapchain marked for reconfiguration, so
+    /// the next call tries again. Headless renderers fail with [`RenderError::NoSurface`].
+    pub fn render_frame(&mut self) -> Result<bool, RenderError> {
+        // Timout to prevent 'hanging' of the image.
+        const TIMOUT: u64 = 1_000_000_000;
+        // How often acquiring is retried within a single frame before giving up on it.
+        const ACQUIRE_ATTEMPTS: u32 = 3;
+
+        let resources: &mut GpuResources<_> = &mut self.resources.0;
+
+        if resources.surface.is_none() {
+            return Err(RenderError::NoSurface);
+        }
+
+        let frame = self.next_frame;
+        self.next_frame = (self.next_frame + 1) % self.frames_in_flight;
+
+        unsafe {
+            use gfx_hal::pool::CommandPool;
+
+            // Resetting the pool whilst its commands still execute is undefined behaviour, so a
+            // slow frame is waited on for as long as it takes.
+            while !resources
+                .device
+                .wait_for_fence(&resources.submission_fences[frame], TIMOUT)?
+            {
+                log::warn!("Frame {} is still in flight, waiting again", frame);
+            }
+
+            resources.command_pools[frame].reset(false);
+        }
+
+        // Acquiring fails when the swapchain no longer matches the surface, e.g. during a resize,
+        // so it's reconfigured and acquired again rather than skipping the frame.
+        let mut attempts = 0;
+        let surface_image = loop {
+            if self.configure_swapchain {
+                self.recreate_swapchain()?;
+            }
+
+            // The surface waits for the image to become available itself, so unlike raw Vulkan
+            // there's no acquire semaphore for the submission to wait on.
+            match unsafe { self.resources.0.surface().acquire_image(TIMOUT) } {
+                Ok((image, suboptimal)) => {
+                    // Still usable for this frame, but should be reconfigured before the next.
+                    self.configure_swapchain |= suboptimal.is_some();
+                    break image;
+                }
+                Err(error) => {
+                    attempts += 1;
+                    self.configure_swapchain = true;
+
+                    if attempts == ACQUIRE_ATTEMPTS {
+                        log::warn!(
+                            "Failed to acquire swapchain image after {} attempts: {}",
+                            attempts,
+                            error
+                        );
+                        return Ok(false);
+                    }
+
+                    log::debug!(
+                        "Failed to acquire swapchain image, reconfiguring: {}",
+                        error
+                    );
+                }
+            }
+        };
+
+        let resources: &mut GpuResources<_> = &mut self.resources.0;
+
+        let (framebuffer, fxaa_framebuffer) = unsafe {
+            use gfx_hal::adapter::PhysicalDevice;
+            use std::borrow::Borrow;
+
+            if let Some(fxaa) = &mut resources.fxaa {
+                fxaa.prepare(
+                    &resources.device,
+                    &self
+                        .adapter
+                        .physical_device
+                        .memory_properties()
+                        .memory_types,
+                    self.surface_extent,
+                    self.surface_color_format,
+                )?;
+            }
+
+            resources.create_framebuffers(
+                surface_image.borrow(),
+                resources.depth_image.as_ref().unwrap(),
+                resources.msaa_image.as_ref(),
+                self.surface_extent,
+            )?
+        };
+
+        let command_buffer = &mut self.command_buffers[frame];
+
+        unsafe {
+            use gfx_hal::command::{CommandBuffer, CommandBufferFlags};
+            use gfx_hal::queue::{CommandQueue, Submission};
+
+            command_buffer.begin_primary(CommandBufferFlags::ONE_TIME_SUBMIT);
+
+            record_scene(
+                command_buffer,
+                resources,
+                &mut self.config,
+                self.frame_log.as_mut(),
+                &self.object_matrices,
+                &framebuffer,
+                self.surface_extent,
+            );
+
+            if let Some(fxaa_framebuffer) = &fxaa_framebuffer {
+                record_fxaa(
+                    command_buffer,
+                    resources,
+                    &self.config,
+                    self.frame_log.as_mut(),
+                    fxaa_framebuffer,
+                    self.surface_extent,
+                );
+            }
+
+            command_buffer.finish();
+
+            // Only reset the fence once it's certain to be submitted, otherwise an early
+            // return above would leave it unsignaled and the next wait would time out.
+            resources
+                .device
+                .reset_fence(&resources.submission_fences[frame])?;
+
+            let submission = Submission {
+                command_buffers: vec![&*command_buffer],
+                wait_semaphores: None,
+                signal_semaphores: vec![&resources.rendering_semaphores[frame]],
+            };
+
+            self.queue_group.queues[0]
+                .submit(submission, Some(&resources.submission_fences[frame]));
+
+            let result = self.queue_group.queues[0].present(
+                resources.surface.as_mut().unwrap(),
+                surface_image,
+                Some(&resources.rendering_semaphores[frame]),
+            );
+
+            // Reconfigured before acquiring the next frame's image.
+            match result {
+                Ok(suboptimal) => self.configure_swapchain |= suboptimal.is_some(),
+                Err(error) => {
+                    log::warn!("Failed to present, reconfiguring the swapchain: {}", error);
+                    self.configure_swapchain = true;
+                }
+            }
+
+            resources.device.destroy_framebuffer(framebuffer);
+            if let Some(fxaa_framebuffer) = fxaa_framebuffer {
+                resources.device.destroy_framebuffer(fxaa_framebuffer);
+            }
+        };
+
+        Ok(true)
+    }
+
+    // Configures the swapchain for the current extent, along with the attachments matching it.
+    fn recreate_swapchain(&mut self) -> Result<(), RenderError> {
+        use gfx_hal::window::SwapchainConfig;
+
+        let resources: &mut GpuResources<_> = &mut self.resources.0;
+
+        let caps = resources
+            .surface()
+            .capabilities(&self.adapter.physical_device);
+
+        let mut swapchain_config =
+            SwapchainConfig::from_caps(&caps, self.surface_color_format, self.surface_extent);
+        swapchain_config.image_usage = swapchain_usage(self.config.swapchain_usage, &caps);
+        swapchain_config.present_mode = present_mode(self.config.present_mode, &caps);
+
+        /*
+        MacOS fullscreen shutdown fix
+        if caps.image_count.contains(&3) {
+            swapchain_config.image_count = 3;
+        }
+        */
+
+        self.surface_extent = swapchain_config.extent;
+        self.frames_in_flight = self.config.frames_in_flight(swapchain_config.image_count);
+
+        unsafe {
+            let GpuResources {
+                surface, device, ..
+            } = resources;
+
+            surface
+                .as_mut()
+                .unwrap()
+                .configure_swapchain(device, swapchain_config)?;
+        };
+
+        unsafe {
+            use gfx_hal::adapter::PhysicalDevice;
+
+            // Frames still in flight may be using the old attachments.
+            resources.device.wait_idle()?;
+
+            if let Some(depth_image) = resources.depth_image.take() {
+                depth_image.destroy(&resources.device);
+            }
+            if let Some(msaa_image) = resources.msaa_image.take() {
+                msaa_image.destroy(&resources.device);
+            }
+
+            let (depth_image, msaa_image) = create_attachments(
+                &resources.device,
+                &self
+                    .adapter
+                    .physical_device
+                    .memory_properties()
+                    .memory_types,
+                self.surface_extent,
+                self.surface_color_format,
+                self.config.anti_alias.samples(),
+            )?;
+            resources.depth_image = Some(depth_image);
+            resources.msaa_image = msaa_image;
+        }
+
+        self.configure_swapchain = false;
+
+        Ok(())
+    }
+
+    /// Renders a single frame into an offscreen image of the given size and format and returns
+    /// its pixels, tightly packed rows of `format`.
+    ///
+    /// Headless renderers rebuild their render pass and pipelines when `format` differs from the
+    /// previous render, starting out with [`HEADLESS_FORMAT`]. Windowed renderers can only render
+    /// in the format of their swapchain.
+    pub fn render_to_image(
+        &mut self,
+        width: u32,
+        height: u32,
+        format: gfx_hal::format::Format,
+    ) -> Result<Vec<u8>, RenderError> {
+        use gfx_hal::adapter::PhysicalDevice;
+        use gfx_hal::image::Usage;
+
+        if width == 0 || height == 0 {
+            return Err(RenderError::EmptyExtent);
+        }
+
+        if format != self.surface_color_format {
+            self.set_color_format(format)?;
+        }
+
+        let memory_types = self
+            .adapter
+            .physical_device
+            .memory_properties()
+            .memory_types;
+        let extent = Extent2D { width, height };
+
+        unsafe {
+            let color = AttachmentImage::<B>::new(
+                &self.resources.0.device,
+                &memory_types,
+                extent,
+                format,
+                Usage::COLOR_ATTACHMENT | Usage::TRANSFER_SRC,
+                1,
+            )?;
+
+            let attachments = create_attachments::<B>(
+                &self.resources.0.device,
+                &memory_types,
+                extent,
+                format,
+                self.config.anti_alias.samples(),
+            );
+            let pixels = attachments.and_then(|(depth, msaa)| {
+                let bytes_per_pixel = format.surface_desc().bits as u64 / 8;
+                let readback = Buffer::<B>::new(
+                    &self.resources.0.device,
+                    &memory_types,
+                    gfx_hal::buffer::Usage::TRANSFER_DST,
+                    width as u64 * height as u64 * bytes_per_pixel,
+                );
+                let pixels = readback.and_then(|readback| {
+                    let pixels = self
+                        .draw_to_buffer(&color, &depth, msaa.as_ref(), &readback, extent)
+                        .and_then(|_| readback.read(&self.resources.0.device));
+                    readback.destroy(&self.resources.0.device);
+                    pixels
+                });
+
+                depth.destroy(&self.resources.0.device);
+                if let Some(msaa) = msaa {
+                    msaa.destroy(&self.resources.0.device);
+                }
+                pixels
+            });
+
+            color.destroy(&self.resources.0.device);
+            pixels
+        }
+    }
+
+    // Recreates the render pass and pipelines of a headless renderer for images of `format`.
+    fn set_color_format(&mut self, format: gfx_hal::format::Format) -> Result<(), RenderError> {
+        use gfx_hal::adapter::PhysicalDevice;
+        use gfx_hal::format::ImageFeature;
+
+        let resources: &mut GpuResources<_> = &mut self.resources.0;
+
+        let features = self
+            .adapter
+            .physical_device
+            .format_properties(Some(format))
+            .optimal_tiling;
+        if resources.surface.is_some() || !features.contains(ImageFeature::COLOR_ATTACHMENT) {
+            return Err(RenderError::UnsupportedFormat(format));
+        }
+
+        unsafe {
+            let render_passes = create_render_passes::<B>(
+                &resources.device,
+                format,
+                self.config.anti_alias.samples(),
+                self.color_final_layout,
+                resources.fxaa.is_some(),
+            )?;
+
+            let mut pipelines = Vec::with_capacity(self.pipeline_descs.len());
+            for desc in &self.pipeline_descs {
+                let pipeline = desc.build::<B>(
+                    &resources.device,
+                    &render_passes,
+                    &resources.pipeline_layouts[desc.layout],
+                    &self.config.assets,
+                    self.config.anti_alias.samples(),
+                    self.config.shader_cache.as_deref(),
+                );
+
+                match pipeline {
+                    Ok(pipeline) => pipelines.push(pipeline),
+                    Err(error) => {
+                        for pipeline in pipelines {
+                            resources.device.destroy_graphics_pipeline(pipeline);
+                        }
+                        for render_pass in render_passes {
+                            resources.device.destroy_render_pass(render_pass);
+                        }
+                        return Err(error);
+                    }
+                }
+            }
+
+            // The previous render may still be using the old pipelines.
+            resources.device.wait_idle()?;
+
+            let old_pipelines = std::mem::replace(&mut resources.pipelines, pipelines);
+            for pipeline in old_pipelines {
+                resources.device.destroy_graphics_pipeline(pipeline);
+            }
+            let old_render_passes = std::mem::replace(&mut resources.render_passes, render_passes);
+            for render_pass in old_render_passes {
+                resources.device.destroy_render_pass(render_pass);
+            }
+        }
+
+        self.surface_color_format = format;
+
+        Ok(())
+    }
+
+    // Renders a frame into `color` and copies it into `readback`, waiting until it's done.
+    unsafe fn draw_to_buffer(
+        &mut self,
+        color: &AttachmentImage<B>,
+        depth: &AttachmentImage<B>,
+        msaa: Option<&AttachmentImage<B>>,
+        readback: &Buffer<B>,
+        extent: Extent2D,
+    ) -> Result<(), RenderError> {
+        use gfx_hal::command::{BufferImageCopy, CommandBuffer, CommandBufferFlags};
+        use gfx_hal::image::{Access, Extent, Layout, Offset, SubresourceLayers};
+        use gfx_hal::memory::{Barrier, Dependencies};
+        use gfx_hal::pool::CommandPool;
+        use gfx_hal::pso::PipelineStage;
+        use gfx_hal::queue::CommandQueue;
+
+        let resources: &mut GpuResources<_> = &mut self.resources.0;
+
+        // Borrows the first frame's command buffer, so every frame has to be done with.
+        resources.device.wait_idle()?;
+        resources.command_pools[0].reset(false);
+
+        if let Some(fxaa) = &mut resources.fxaa {
+            use gfx_hal::adapter::PhysicalDevice;
+
+            fxaa.prepare(
+                &resources.device,
+                &self
+                    .adapter
+                    .physical_device
+                    .memory_properties()
+                    .memory_types,
+                extent,
+                self.surface_color_format,
+            )?;
+        }
+
+        let (framebuffer, fxaa_framebuffer) =
+            resources.create_framebuffers(&color.view, depth, msaa, extent)?;
+
+        let command_buffer = &mut self.command_buffers[0];
+        command_buffer.begin_primary(CommandBufferFlags::ONE_TIME_SUBMIT);
+
+        record_scene(
+            command_buffer,
+            resources,
+            &mut self.config,
+            self.frame_log.as_mut(),
+            &self.object_matrices,
+            &framebuffer,
+            extent,
+        );
+
+        if let Some(fxaa_framebuffer) = &fxaa_framebuffer {
+            record_fxaa(
+                command_buffer,
+                resources,
+                &self.config,
+                self.frame_log.as_mut(),
+                fxaa_framebuffer,
+                extent,
+            );
+        }
+
+        command_buffer.pipeline_barrier(
+            PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::TRANSFER,
+            Dependencies::empty(),
+            &[Barrier::Image {
+                states: (Access::COLOR_ATTACHMENT_WRITE, self.color_final_layout)
+                    ..(Access::TRANSFER_READ, Layout::TransferSrcOptimal),
+                target: &color.image,
+                range: attachment::COLOR_RANGE,
+                families: None,
+            }],
+        );
+
+        command_buffer.copy_image_to_buffer(
+            &color.image,
+            Layout::TransferSrcOptimal,
+            &readback.buffer,
+            &[BufferImageCopy {
+                buffer_offset: 0,
+                buffer_width: extent.width,
+                buffer_height: extent.height,
+                image_layers: SubresourceLayers {
+                    aspects: gfx_hal::format::Aspects::COLOR,
+                    level: 0,
+                    layers: 0..1,
+                },
+                image_offset: Offset::ZERO,
+                image_extent: Extent {
+                    width: extent.width,
+                    height: extent.height,
+                    depth: 1,
+                },
+            }],
+        );
+
+        command_buffer.pipeline_barrier(
+            PipelineStage::TRANSFER..PipelineStage::HOST,
+            Dependencies::empty(),
+            &[Barrier::whole_buffer(
+                &readback.buffer,
+                gfx_hal::buffer::Access::TRANSFER_WRITE..gfx_hal::buffer::Access::HOST_READ,
+            )],
+        );
+
+        command_buffer.finish();
+
+        let fence = &resources.submission_fences[0];
+        let finished = match resources.device.reset_fence(fence) {
+            Ok(()) => {
+                self.queue_group.queues[0]
+                    .submit_without_semaphores(std::iter::once(&*command_buffer), Some(fence));
+                resources
+                    .device
+                    .wait_for_fence(fence, !0)
+                    .map_err(RenderError::from)
+            }
+            Err(error) => Err(error.into()),
+        };
+
+        resources.device.destroy_framebuffer(framebuffer);
+        if let Some(fxaa_framebuffer) = fxaa_framebuffer {
+            resources.device.destroy_framebuffer(fxaa_framebuffer);
+        }
+
+        finished?;
+        Ok(())
+    }
+}
+
+/// Color format of the images rendered by headless renderers.
+pub const HEADLESS_FORMAT: gfx_hal::format::Format = gfx_hal::format::Format::Rgba8Srgb;
+
+// Render pass drawing the scene into a color image of `color_format`, left in `final_layout`.
+unsafe fn create_render_pass<B: gfx_hal::Backend>(
+    device: &B::Device,
+    color_format: gfx_hal::format::Format,
+    samples: gfx_hal::image::NumSamples,
+    final_layout: gfx_hal::image::Layout,
+) -> Result<B::RenderPass, RenderError> {
+    use gfx_hal::image::Layout;
+    use gfx_hal::pass::{
+        Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp, SubpassDesc,
+    };
+
+    // With MSAA the scene is rendered into a multisampled image, which is resolved into
+    // the final image at the end of the subpass. Only the resolved samples are stored.
+    let multisampled = samples > 1;
+
+    let attachment = Attachment {
+        format: Some(color_format),
+        samples,
+        ops: AttachmentOps::new(
+            AttachmentLoadOp::Clear,
+            if multisampled {
+                AttachmentStoreOp::DontCare
+            } else {
+                AttachmentStoreOp::Store
+            },
+        ),
+        stencil_ops: AttachmentOps::DONT_CARE,
+        layouts: Layout::Undefined..if multisampled {
+            Layout::ColorAttachmentOptimal
+        } else {
+            final_layout
+        },
+    };
+
+    // Depth is only needed whilst rendering, so it's never stored.
+    let depth_attachment = Attachment {
+        format: Some(attachment::DEPTH_FORMAT),
+        samples,
+        ops: AttachmentOps::new(AttachmentLoadOp::Clear, AttachmentStoreOp::DontCare),
+        stencil_ops: AttachmentOps::DONT_CARE,
+        layouts: Layout::Undefined..Layout::DepthStencilAttachmentOptimal,
+    };
+
+    // colors: is refering to the first index of the list of attachments
+    // passed into create_render_pass.
+    let subpass = SubpassDesc {
+        colors: &[(0, Layout::ColorAttachmentOptimal)],
+        depth_stencil: Some(&(1, Layout::DepthStencilAttachmentOptimal)),
+        inputs: &[],
+        resolves: if multisampled {
+            &[(2, Layout::ColorAttachmentOptimal)]
+        } else {
+            &[]
+        },
+        preserves: &[],
+    };
+
+    let mut attachments = vec![attachment, depth_attachment];
+
+    if multisampled {
+        attachments.push(Attachment {
+            format: Some(color_format),
+            samples: 1,
+            ops: AttachmentOps::new(AttachmentLoadOp::DontCare, AttachmentStoreOp::Store),
+            stencil_ops: AttachmentOps::DONT_CARE,
+            layouts: Layout::Undefined..final_layout,
+        });
+    }
+
+    Ok(device.create_render_pass(attachments, &[subpass], &[])?)
+}
+
+// The scene's render pass, followed with FXAA by the pass filtering the scene into the final image.
+unsafe fn create_render_passes<B: gfx_hal::Backend>(
+    device: &B::Device,
+    color_format: gfx_hal::format::Format,
+    samples: gfx_hal::image::NumSamples,
+    final_layout: gfx_hal::image::Layout,
+    fxaa: bool,
+) -> Result<Vec<B::RenderPass>, RenderError> {
+    use gfx_hal::image::Layout;
+    use gfx_hal::pass::{
+        Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp, SubpassDesc,
+    };
+
+    if !fxaa {
+        return Ok(vec![create_render_pass::<B>(
+            device,
+            color_format,
+            samples,
+            final_layout,
+        )?]);
+    }
+
+    // The scene is sampled by the FXAA pass rather than presented or copied.
+    let scene =
+        create_render_pass::<B>(device, color_format, samples, Layout::ShaderReadOnlyOptimal)?;
+
+    // Every pixel is written by the full-screen triangle, the clear only keeps the pixels defined
+    // where the scene is translucent.
+    let attachment = Attachment {
+        format: Some(color_format),
+        samples: 1,
+        ops: AttachmentOps::new(AttachmentLoadOp::Clear, AttachmentStoreOp::Store),
+        stencil_ops: AttachmentOps::DONT_CARE,
+        layouts: Layout::Undefined..final_layout,
+    };
+    let subpass = SubpassDesc {
+        colors: &[(0, Layout::ColorAttachmentOptimal)],
+        depth_stencil: None,
+        inputs: &[],
+        resolves: &[],
+        preserves: &[],
+    };
+
+    match device.create_render_pass(std::iter::once(attachment), &[subpass], &[]) {
+        Ok(fxaa) => Ok(vec![scene, fxaa]),
+        Err(error) => {
+            device.destroy_render_pass(scene);
+            Err(error.into())
+        }
+    }
+}
+
+// Creates the depth image and, when multisampling, the color image that's resolved into the final
+// image at the end of the render pass.
+unsafe fn create_attachments<B: gfx_hal::Backend>(
+    device: &B::Device,
+    memory_types: &[gfx_hal::adapter::MemoryType],
+    extent: Extent2D,
+    color_format: gfx_hal::format::Format,
+    samples: gfx_hal::image::NumSamples,
+) -> Result<(AttachmentImage<B>, Option<AttachmentImage<B>>), RenderError> {
+    let depth = AttachmentImage::depth(device, memory_types, extent, samples)?;
+    if samples == 1 {
+        return Ok((depth, None));
+    }
+
+    match AttachmentImage::multisampled(device, memory_types, extent, color_format, samples) {
+        Ok(msaa) => Ok((depth, Some(msaa))),
+        Err(error) => {
+            depth.destroy(device);
+            Err(error)
+        }
+    }
+}
+
+// Framebuffer rendering into `target`, directly or by resolving `msaa` into it. The attachments
+// are ordered the same as in the render pass.
+unsafe fn create_framebuffer<B: gfx_hal::Backend>(
+    device: &B::Device,
+    render_pass: &B::RenderPass,
+    target: &B::ImageView,
+    depth: &AttachmentImage<B>,
+    msaa: Option<&AttachmentImage<B>>,
+    extent: Extent2D,
+) -> Result<B::Framebuffer, gfx_hal::device::OutOfMemory> {
+    let attachments = match msaa {
+        Some(msaa) => vec![&msaa.view, &depth.view, target],
+        None => vec![target, &depth.view],
+    };
+
+    device.create_framebuffer(
+        render_pass,
+        attachments,
+        gfx_hal::image::Extent {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        },
+    )
+}
+
+// Records the main render pass with all the built-in draws, followed by any custom draw. The
+// command buffer must have begun recording.
+unsafe fn record_scene<B: gfx_hal::Backend>(
+    command_buffer: &mut B::CommandBuffer,
+    resources: &GpuResources<B>,
+    config: &mut RenderConfig,
+    frame_log: Option<&mut FrameLog>,
+    object_matrices: &[math::Mat4],
+    framebuffer: &B::Framebuffer,
+    extent: Extent2D,
+) {
+    use gfx_hal::command::CommandBuffer;
+    use gfx_hal::pso::ShaderStageFlags;
+
+    let viewport = gfx_hal::pso::Viewport {
+        rect: gfx_hal::pso::Rect {
+            x: 0,
+            y: 0,
+            w: extent.width as i16,
+            h: extent.height as i16,
+        },
+        depth: 0.0..1.0,
+    };
+
+    command_buffer.set_viewports(0, std::slice::from_ref(&viewport));
+    command_buffer.set_scissors(0, [viewport.rect]);
+
+    let mut recorder = CommandRecorder::<B>::new(command_buffer, frame_log);
+
+    recorder.begin_render_pass(
+        (0, &resources.render_passes[0]),
+        framebuffer,
+        viewport.rect,
+        config.clear_color,
+    );
+
+    if !config.clear_only {
+        // Textures are drawn onto the quad with their own pipeline, which shares the transform
+        // with the main one but has no color adjustment.
+        let (pipeline, layout) = match resources.texture {
+            Some(_) if config.wireframe => (4, 2),
+            Some(_) => (3, 2),
+            None if config.wireframe => (2, 0),
+            None => (0, 0),
+        };
+
+        recorder.bind_graphics_pipeline((pipeline, &resources.pipelines[pipeline]));
+
+        match &resources.texture {
+            Some((_, descriptor_set)) => recorder.bind_graphics_descriptor_sets(
+                (layout, &resources.pipeline_layouts[layout]),
+                0,
+                &[(0, descriptor_set)],
+            ),
+            None => recorder.push_graphics_constants(
+                (layout, &resources.pipeline_layouts[layout]),
+                ShaderStageFlags::FRAGMENT,
+                TRANSFORM_PUSH_CONSTANTS_SIZE,
+                &config.color_adjustment.push_constants(),
+            ),
+        }
+
+        let mesh_idx = if config.quad || resources.texture.is_some() {
+            1
+        } else {
+            0
+        };
+        // The batch's buffers are logged after the individual meshes.
+        let (buffer_idx, mesh) = match &resources.mesh_batch {
+            Some(mesh_batch) => (resources.meshes.len(), &mesh_batch.mesh),
+            None => (mesh_idx, &resources.meshes[mesh_idx]),
+        };
+        recorder.bind_vertex_buffers(0, &[(buffer_idx, &mesh.vertex_buffer.buffer)]);
+
+        if let Some(index_buffer) = &mesh.index_buffer {
+            recorder.bind_index_buffer(
+                (buffer_idx, &index_buffer.buffer.buffer),
+                index_buffer.index_type,
+            );
+        }
+
+        // Without a scene there's a single object placed by the transform alone.
+        let objects = if object_matrices.is_empty() {
+            std::slice::from_ref(&math::IDENTITY)
+        } else {
+            object_matrices
+        };
+
+        for world in objects {
+            recorder.push_graphics_constants(
+                (layout, &resources.pipeline_layouts[layout]),
+                ShaderStageFlags::VERTEX,
+                0,
+                &math::push_constants(&math::mul(&config.transform, world)),
+            );
+
+            match (&resources.mesh_batch, &mesh.index_buffer) {
+                (Some(mesh_batch), _) => recorder.draw_indexed_indirect(
+                    (buffer_idx, &mesh_batch.indirect_buffer.buffer),
+                    mesh_batch.command_offset(mesh_idx),
+                    1,
+                    DrawIndexedCommand::STRIDE,
+                ),
+                (None, Some(index_buffer)) => {
+                    recorder.draw_indexed(index_buffer.indices(), 0, 0..1)
+                }
+                (None, None) => recorder.draw(mesh.vertices(), 0..1),
+            }
+        }
+
+        if config.show_grid {
+            recorder.bind_graphics_pipeline((1, &resources.pipelines[1]));
+            recorder.push_graphics_constants(
+                (1, &resources.pipeline_layouts[1]),
+                ShaderStageFlags::VERTEX,
+                0,
+                &math::push_constants(&config.grid.view_projection(extent.width, extent.height)),
+            );
+            recorder.push_graphics_constants(
+                (1, &resources.pipeline_layouts[1]),
+                ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT,
+                TRANSFORM_PUSH_CONSTANTS_SIZE,
+                &config.grid.push_constants(),
+            );
+            recorder.draw(0..6, 0..1);
+        }
+    }
+
+    if let Some(custom_draw) = config.custom_draw.as_mut() {
+        recorder.custom(|command_buffer| custom_draw(command_buffer));
+    }
+
+    recorder.end_render_pass();
+}
+
+// Records the FXAA pass, filtering the scene recorded by `record_scene` into the framebuffer's
+// image. The scene's render pass must have ended.
+unsafe fn record_fxaa<B: gfx_hal::Backend>(
+    command_buffer: &mut B::CommandBuffer,
+    resources: &GpuResources<B>,
+    config: &RenderConfig,
+    frame_log: Option<&mut FrameLog>,
+    framebuffer: &B::Framebuffer,
+    extent: Extent2D,
+) {
+    use gfx_hal::command::CommandBuffer;
+    use gfx_hal::image::{Access, Layout};
+    use gfx_hal::memory::{Barrier, Dependencies};
+    use gfx_hal::pso::{PipelineStage, ShaderStageFlags};
+
+    // The pipeline and layout pushed last when FXAA is enabled.
+    const PIPELINE: usize = 5;
+    const LAYOUT: usize = 3;
+
+    let fxaa = resources.fxaa.as_ref().expect("FXAA isn't enabled");
+
+    // The scene's render pass already left the image in `ShaderReadOnlyOptimal`, but its writes
+    // have to be made visible to the fragment shader sampling it.
+    command_buffer.pipeline_barrier(
+        PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::FRAGMENT_SHADER,
+        Dependencies::empty(),
+        &[Barrier::Image {
+            states: (
+                Access::COLOR_ATTACHMENT_WRITE,
+                Layout::ShaderReadOnlyOptimal,
+            )..(Access::SHADER_READ, Layout::ShaderReadOnlyOptimal),
+            target: &fxaa.image().image,
+            range: attachment::COLOR_RANGE,
+            families: None,
+        }],
+    );
+
+    let mut recorder = CommandRecorder::<B>::new(command_buffer, frame_log);
+
+    recorder.begin_render_pass(
+        (1, &resources.render_passes[1]),
+        framebuffer,
+        gfx_hal::pso::Rect {
+            x: 0,
+            y: 0,
+            w: extent.width as i16,
+            h: extent.height as i16,
+        },
+        config.clear_color,
+    );
+
+    recorder.bind_graphics_pipeline((PIPELINE, &resources.pipelines[PIPELINE]));
+    // Logged after the texture's descriptor set.
+    recorder.bind_graphics_descriptor_sets(
+        (LAYOUT, &resources.pipeline_layouts[LAYOUT]),
+        0,
+        &[(1, &fxaa.descriptor_set)],
+    );
+    recorder.push_graphics_constants(
+        (LAYOUT, &resources.pipeline_layouts[LAYOUT]),
+        ShaderStageFlags::FRAGMENT,
+        0,
+        &[
+            (1.0 / extent.width as f32).to_bits(),
+            (1.0 / extent.height as f32).to_bits(),
+        ],
+    );
+    recorder.draw(0..3, 0..1);
+
+    recorder.end_render_pass();
+}
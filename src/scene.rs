@@ -0,0 +1,255 @@
+use crate::math::{self, Mat4, Quat};
+
+/// Translation, rotation and scale of a node relative to its parent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: [f32; 3],
+    pub rotation: Quat,
+    pub scale: [f32; 3],
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        translation: [0.0; 3],
+        rotation: math::QUAT_IDENTITY,
+        scale: [1.0; 3],
+    };
+
+    /// Equivalent to `translation * rotation * scale`.
+    pub fn matrix(&self) -> Mat4 {
+        let [x, y, z, w] = self.rotation;
+        let [sx, sy, sz] = self.scale;
+        let [tx, ty, tz] = self.translation;
+
+        [
+            [
+                (1.0 - 2.0 * (y * y + z * z)) * sx,
+                2.0 * (x * y + w * z) * sx,
+                2.0 * (x * z - w * y) * sx,
+                0.0,
+            ],
+            [
+                2.0 * (x * y - w * z) * sy,
+                (1.0 - 2.0 * (x * x + z * z)) * sy,
+                2.0 * (y * z + w * x) * sy,
+                0.0,
+            ],
+            [
+                2.0 * (x * z + w * y) * sz,
+                2.0 * (y * z - w * x) * sz,
+                (1.0 - 2.0 * (x * x + y * y)) * sz,
+                0.0,
+            ],
+            [tx, ty, tz, 1.0],
+        ]
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// Handle to a node inside a [`SceneGraph`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+#[derive(Debug, Clone)]
+pub struct SceneNode {
+    local: Transform,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+impl SceneNode {
+    pub fn local_transform(&self) -> &Transform {
+        &self.local
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+/// Hierarchy of nodes where each node's world matrix is its parent's world matrix multiplied by
+/// its own local transform.
+///
+/// Nodes are stored in a flat list and referenced by [`NodeId`], a node is always added after its
+/// parent so walking the list in order visits parents first.
+#[derive(Debug, Clone, Default)]
+pub struct SceneGraph {
+    nodes: Vec<SceneNode>,
+}
+
+impl SceneGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_root(&mut self, local: Transform) -> NodeId {
+        self.insert(local, None)
+    }
+
+    pub fn add_child(&mut self, parent: NodeId, local: Transform) -> NodeId {
+        let child = self.insert(local, Some(parent));
+        self.nodes[parent.0].children.push(child);
+        child
+    }
+
+    fn insert(&mut self, local: Transform, parent: Option<NodeId>) -> NodeId {
+        self.nodes.push(SceneNode {
+            local,
+            parent,
+            children: Vec::new(),
+        });
+
+        NodeId(self.nodes.len() - 1)
+    }
+
+    pub fn node(&self, id: NodeId) -> &SceneNode {
+        &self.nodes[id.0]
+    }
+
+    pub fn set_local_transform(&mut self, id: NodeId, local: Transform) {
+        self.nodes[id.0].local = local;
+    }
+
+    /// Walks up the hierarchy to compute the node's transform relative to the world origin.
+    pub fn world_matrix(&self, id: NodeId) -> Mat4 {
+        let node = &self.nodes[id.0];
+        let local = node.local.matrix();
+
+        match node.parent {
+            Some(parent) => math::mul(&self.world_matrix(parent), &local),
+            None => local,
+        }
+    }
+
+    /// Computes the world matrix of every node in a single pass, indexed by `NodeId`.
+    ///
+    /// This is what should be called each frame, as it avoids walking the same parents
+    /// repeatedly like calling [`SceneGraph::world_matrix`] for each node would.
+    pub fn world_matrices(&self, out: &mut Vec<Mat4>) {
+        out.clear();
+
+        for node in &self.nodes {
+            let local = node.local.matrix();
+            let world = match node.parent {
+                Some(parent) => math::mul(&out[parent.0], &local),
+                None => local,
+            };
+
+            out.push(world);
+        }
+    }
+
+    /// World matrices of the nodes without children, in the same order as
+    /// [`SceneGraph::leaves`].
+    pub fn leaf_world_matrices(&self, out: &mut Vec<Mat4>) {
+        self.world_matrices(out);
+
+        let mut nodes = self.nodes.iter();
+        out.retain(|_| nodes.next().is_some_and(|node| node.children.is_empty()));
+    }
+
+    /// Nodes without children, these are the ones that end up being drawn.
+    pub fn leaves(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.children.is_empty())
+            .map(|(idx, _)| NodeId(idx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_matrix_eq(actual: &Mat4, expected: &Mat4) {
+        let close = actual
+            .iter()
+            .flatten()
+            .zip(expected.iter().flatten())
+            .all(|(a, b)| (a - b).abs() < 1e-5);
+
+        assert!(close, "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn child_rotation_is_applied_within_root_translation() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add_root(Transform {
+            translation: [1.0, 2.0, 3.0],
+            ..Transform::IDENTITY
+        });
+        let child = graph.add_child(
+            root,
+            Transform {
+                translation: [1.0, 0.0, 0.0],
+                rotation: math::quat_from_axis_angle([0.0, 0.0, 1.0], std::f32::consts::FRAC_PI_2),
+                ..Transform::IDENTITY
+            },
+        );
+
+        // The child's x axis is rotated onto y, and its origin is offset by both translations.
+        let expected = [
+            [0.0, 1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [2.0, 2.0, 3.0, 1.0],
+        ];
+        assert_matrix_eq(&graph.world_matrix(child), &expected);
+
+        let mut matrices = Vec::new();
+        graph.world_matrices(&mut matrices);
+        assert_matrix_eq(&matrices[root.0], &graph.world_matrix(root));
+        assert_matrix_eq(&matrices[child.0], &expected);
+    }
+
+    #[test]
+    fn leaves_are_nodes_without_children() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add_root(Transform::IDENTITY);
+        let branch = graph.add_child(root, Transform::IDENTITY);
+        let leaf = graph.add_child(root, Transform::IDENTITY);
+        let nested_leaf = graph.add_child(branch, Transform::IDENTITY);
+
+        assert_eq!(graph.leaves().collect::<Vec<_>>(), [leaf, nested_leaf]);
+        assert_eq!(graph.node(branch).children(), [nested_leaf]);
+        assert_eq!(graph.node(nested_leaf).parent(), Some(branch));
+    }
+
+    #[test]
+    fn leaf_world_matrices_follow_leaves() {
+        let translated = |x| Transform {
+            translation: [x, 0.0, 0.0],
+            ..Transform::IDENTITY
+        };
+
+        let mut graph = SceneGraph::new();
+        let root = graph.add_root(translated(1.0));
+        let branch = graph.add_child(root, translated(2.0));
+        graph.add_child(root, translated(4.0));
+        graph.add_child(branch, translated(8.0));
+
+        let mut matrices = Vec::new();
+        graph.leaf_world_matrices(&mut matrices);
+        let expected: Vec<_> = graph
+            .leaves()
+            .map(|leaf| graph.world_matrix(leaf))
+            .collect();
+
+        assert_eq!(matrices.len(), 2);
+        for (actual, expected) in matrices.iter().zip(&expected) {
+            assert_matrix_eq(actual, expected);
+        }
+        assert_eq!(matrices[0][3][0], 5.0);
+        assert_eq!(matrices[1][3][0], 11.0);
+    }
+}
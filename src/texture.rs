@@ -0,0 +1,293 @@
+use std::path::Path;
+
+use gfx_hal::adapter::{MemoryType, PhysicalDevice};
+use gfx_hal::command::{BufferImageCopy, CommandBuffer, CommandBufferFlags, Level};
+use gfx_hal::device::Device;
+use gfx_hal::format::{Aspects, Format, ImageFeature};
+use gfx_hal::image::{
+    Access, Extent, Filter, Layout, Offset, SamplerDesc, SubresourceLayers, Usage, WrapMode,
+};
+use gfx_hal::memory::{Barrier, Dependencies};
+use gfx_hal::pool::CommandPool;
+use gfx_hal::pso::PipelineStage;
+use gfx_hal::queue::CommandQueue;
+use gfx_hal::window::Extent2D;
+
+use crate::assets::AssetResolver;
+use crate::attachment::{self, AttachmentImage};
+use crate::buffer::Buffer;
+use crate::compressed::CompressedImage;
+use crate::RenderError;
+
+/// Format textures are stored in, matching the RGBA pixels decoded by the image crate.
+pub const TEXTURE_FORMAT: Format = Format::Rgba8Srgb;
+
+/// A device local image sampled by shaders, along with its sampler.
+pub struct Texture<B: gfx_hal::Backend> {
+    pub image: AttachmentImage<B>,
+    pub sampler: B::Sampler,
+}
+
+impl<B: gfx_hal::Backend> Texture<B> {
+    /// Copies `pixels` into a new texture through a staging buffer, waiting for the copy to
+    /// complete. The texture is left in `ShaderReadOnlyOptimal` layout.
+    ///
+    /// # Safety
+    ///
+    /// `memory_types` must have been queried from the physical device `device` was opened on and
+    /// `command_pool` must belong to the queue family of `queue`.
+    pub unsafe fn upload(
+        device: &B::Device,
+        memory_types: &[MemoryType],
+        command_pool: &mut B::CommandPool,
+        queue: &mut B::CommandQueue,
+        pixels: &image::RgbaImage,
+    ) -> Result<Self, RenderError> {
+        let (width, height) = pixels.dimensions();
+
+        Self::upload_texels(
+            device,
+            memory_types,
+            command_pool,
+            queue,
+            Extent2D { width, height },
+            TEXTURE_FORMAT,
+            pixels.as_raw(),
+        )
+    }
+
+    /// Copies the blocks of `image` into a new texture in `format`, which must be one of
+    /// `image.formats`, without decompressing them. Otherwise the same as [`Texture::upload`].
+    ///
+    /// # Safety
+    ///
+    /// Same requirements as [`Texture::upload`], and the device must support sampling `format`
+    /// with optimal tiling.
+    pub unsafe fn upload_compressed(
+        device: &B::Device,
+        memory_types: &[MemoryType],
+        command_pool: &mut B::CommandPool,
+        queue: &mut B::CommandQueue,
+        image: &CompressedImage,
+        format: Format,
+    ) -> Result<Self, RenderError> {
+        Self::upload_texels(
+            device,
+            memory_types,
+            command_pool,
+            queue,
+            Extent2D {
+                width: image.width,
+                height: image.height,
+            },
+            format,
+            &image.data,
+        )
+    }
+
+    unsafe fn upload_texels(
+        device: &B::Device,
+        memory_types: &[MemoryType],
+        command_pool: &mut B::CommandPool,
+        queue: &mut B::CommandQueue,
+        extent: Extent2D,
+        format: Format,
+        texels: &[u8],
+    ) -> Result<Self, RenderError> {
+        let staging = Buffer::<B>::upload(
+            device,
+            memory_types,
+            gfx_hal::buffer::Usage::TRANSFER_SRC,
+            texels,
+        )?;
+        let image = AttachmentImage::new(
+            device,
+            memory_types,
+            extent,
+            format,
+            Usage::TRANSFER_DST | Usage::SAMPLED,
+            1,
+        );
+        let image = match image {
+            Ok(image) => image,
+            Err(error) => {
+                staging.destroy(device);
+                return Err(error);
+            }
+        };
+
+        let copied = copy_to_image::<B>(
+            device,
+            command_pool,
+            queue,
+            &staging,
+            &image,
+            extent,
+            format,
+        );
+        staging.destroy(device);
+
+        let sampler = copied.and_then(|_| {
+            Ok(device.create_sampler(&SamplerDesc::new(Filter::Linear, WrapMode::Clamp))?)
+        });
+
+        match sampler {
+            Ok(sampler) => Ok(Self { image, sampler }),
+            Err(error) => {
+                image.destroy(device);
+                Err(error)
+            }
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The texture must have been created by `device` and no longer be in use by the GPU.
+    pub unsafe fn destroy(self, device: &B::Device) {
+        device.destroy_sampler(self.sampler);
+        self.image.destroy(device);
+    }
+}
+
+/// Reads a DDS or KTX2 file through `assets` and uploads it in the first of its formats the
+/// device can sample, without decompressing it.
+///
+/// Fails with [`RenderError::NoCompressedFormat`] listing the file's formats when the device
+/// supports none of them, e.g. BC formats on most mobile GPUs or ETC2 and ASTC on most desktops.
+///
+/// # Safety
+///
+/// Same requirements as [`Texture::upload`], and `device` must have been opened on
+/// `physical_device`.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn load_compressed_texture<B: gfx_hal::Backend>(
+    physical_device: &B::PhysicalDevice,
+    device: &B::Device,
+    memory_types: &[MemoryType],
+    command_pool: &mut B::CommandPool,
+    queue: &mut B::CommandQueue,
+    assets: &AssetResolver,
+    path: impl AsRef<Path>,
+) -> Result<Texture<B>, RenderError> {
+    let bytes = assets.read(path).map_err(image::ImageError::IoError)?;
+    let image = CompressedImage::parse(&bytes)?;
+
+    let format = image
+        .formats
+        .iter()
+        .copied()
+        .find(|&format| {
+            physical_device
+                .format_properties(Some(format))
+                .optimal_tiling
+                .contains(ImageFeature::SAMPLED)
+        })
+        .ok_or_else(|| RenderError::NoCompressedFormat(image.formats.clone()))?;
+
+    Texture::upload_compressed(device, memory_types, command_pool, queue, &image, format)
+}
+
+/// Whether `path` names a DDS or KTX2 file by its extension, which should be loaded with
+/// [`load_compressed_texture`] rather than decoded by the image crate.
+pub fn is_compressed_texture(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("dds") || extension.eq_ignore_ascii_case("ktx2")
+        })
+}
+
+// Copies `staging` into `image`, transitioning it to `ShaderReadOnlyOptimal`, and waits for the
+// copy to complete.
+unsafe fn copy_to_image<B: gfx_hal::Backend>(
+    device: &B::Device,
+    command_pool: &mut B::CommandPool,
+    queue: &mut B::CommandQueue,
+    staging: &Buffer<B>,
+    image: &AttachmentImage<B>,
+    Extent2D { width, height }: Extent2D,
+    format: Format,
+) -> Result<(), RenderError> {
+    // Rows of compressed formats are made of whole blocks, even when the image isn't.
+    let (block_width, block_height) = format.surface_desc().dim;
+    let buffer_width = width.div_ceil(u32::from(block_width)) * u32::from(block_width);
+    let buffer_height = height.div_ceil(u32::from(block_height)) * u32::from(block_height);
+
+    let mut command_buffer = command_pool.allocate_one(Level::Primary);
+    command_buffer.begin_primary(CommandBufferFlags::ONE_TIME_SUBMIT);
+
+    command_buffer.pipeline_barrier(
+        PipelineStage::TOP_OF_PIPE..PipelineStage::TRANSFER,
+        Dependencies::empty(),
+        &[Barrier::Image {
+            states: (Access::empty(), Layout::Undefined)
+                ..(Access::TRANSFER_WRITE, Layout::TransferDstOptimal),
+            target: &image.image,
+            range: attachment::COLOR_RANGE,
+            families: None,
+        }],
+    );
+
+    command_buffer.copy_buffer_to_image(
+        &staging.buffer,
+        &image.image,
+        Layout::TransferDstOptimal,
+        &[BufferImageCopy {
+            buffer_offset: 0,
+            buffer_width,
+            buffer_height,
+            image_layers: SubresourceLayers {
+                aspects: Aspects::COLOR,
+                level: 0,
+                layers: 0..1,
+            },
+            image_offset: Offset::ZERO,
+            image_extent: Extent {
+                width,
+                height,
+                depth: 1,
+            },
+        }],
+    );
+
+    command_buffer.pipeline_barrier(
+        PipelineStage::TRANSFER..PipelineStage::FRAGMENT_SHADER,
+        Dependencies::empty(),
+        &[Barrier::Image {
+            states: (Access::TRANSFER_WRITE, Layout::TransferDstOptimal)
+                ..(Access::SHADER_READ, Layout::ShaderReadOnlyOptimal),
+            target: &image.image,
+            range: attachment::COLOR_RANGE,
+            families: None,
+        }],
+    );
+
+    command_buffer.finish();
+
+    let finished = device
+        .create_fence(false)
+        .map_err(RenderError::from)
+        .and_then(|fence| {
+            queue.submit_without_semaphores(std::iter::once(&command_buffer), Some(&fence));
+            let finished = device.wait_for_fence(&fence, !0);
+            device.destroy_fence(fence);
+            Ok(finished?)
+        });
+
+    command_pool.free(std::iter::once(command_buffer));
+
+    finished.map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressed_textures_are_detected_by_extension() {
+        assert!(is_compressed_texture(Path::new("textures/brick.dds")));
+        assert!(is_compressed_texture(Path::new("textures/brick.KTX2")));
+        assert!(!is_compressed_texture(Path::new("textures/brick.png")));
+        assert!(!is_compressed_texture(Path::new("textures/ktx2")));
+    }
+}
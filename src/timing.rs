@@ -0,0 +1,123 @@
+use std::time::{Duration, Instant};
+
+/// How many of the most recent frames [`FrameTimer`] averages over.
+pub const AVERAGED_FRAMES: usize = 120;
+
+/// Measures the time between consecutive frames, averaged over the last [`AVERAGED_FRAMES`].
+#[derive(Debug, Clone)]
+pub struct FrameTimer {
+    last_frame: Option<Instant>,
+    // Ring buffer of the most recent frame times, of which the first `count` are valid.
+    frame_times: [Duration; AVERAGED_FRAMES],
+    next: usize,
+    count: usize,
+    total: Duration,
+}
+
+impl FrameTimer {
+    pub fn new() -> Self {
+        Self {
+            last_frame: None,
+            frame_times: [Duration::ZERO; AVERAGED_FRAMES],
+            next: 0,
+            count: 0,
+            total: Duration::ZERO,
+        }
+    }
+
+    /// Marks the start of a frame. The first call after creating or resetting the timer only
+    /// starts measuring, as there's no previous frame to measure against.
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+        let last_frame = match self.last_frame.replace(now) {
+            Some(last_frame) => last_frame,
+            None => return,
+        };
+
+        self.record(now - last_frame);
+    }
+
+    /// Adds a measured frame time to the average, replacing the oldest one once
+    /// [`AVERAGED_FRAMES`] have been recorded.
+    pub fn record(&mut self, frame_time: Duration) {
+        if self.count == AVERAGED_FRAMES {
+            self.total -= self.frame_times[self.next];
+        } else {
+            self.count += 1;
+        }
+
+        self.frame_times[self.next] = frame_time;
+        self.total += frame_time;
+        self.next = (self.next + 1) % AVERAGED_FRAMES;
+    }
+
+    /// Stops measuring until the next [`FrameTimer::tick`], so pauses in rendering don't count
+    /// as a long frame. The previous frame times are kept.
+    pub fn reset(&mut self) {
+        self.last_frame = None;
+    }
+
+    /// Average frame time, `None` until at least one frame has been measured.
+    pub fn average(&self) -> Option<Duration> {
+        match self.count {
+            0 => None,
+            count => Some(self.total / count as u32),
+        }
+    }
+}
+
+impl Default for FrameTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fps(timer: &FrameTimer) -> f64 {
+        1.0 / timer.average().unwrap().as_secs_f64()
+    }
+
+    #[test]
+    fn first_tick_only_starts_measuring() {
+        let mut timer = FrameTimer::new();
+        timer.tick();
+        assert_eq!(timer.average(), None);
+
+        timer.record(Duration::from_millis(10));
+        timer.reset();
+        timer.tick();
+        assert_eq!(timer.average(), Some(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn average_covers_the_most_recent_frames() {
+        let mut timer = FrameTimer::new();
+
+        timer.record(Duration::from_millis(10));
+        timer.record(Duration::from_millis(30));
+        assert_eq!(timer.average(), Some(Duration::from_millis(20)));
+
+        for _ in 2..AVERAGED_FRAMES {
+            timer.record(Duration::from_millis(20));
+        }
+        assert_eq!(timer.average(), Some(Duration::from_millis(20)));
+        assert!((fps(&timer) - 50.0).abs() < 1e-6);
+
+        // Past the wraparound the oldest frames are replaced, starting with the 10 and 30 ms ones.
+        timer.record(Duration::from_millis(10));
+        timer.record(Duration::from_millis(10));
+        assert_eq!(
+            timer.average(),
+            Some(Duration::from_millis(20 * 118 + 10 * 2) / AVERAGED_FRAMES as u32)
+        );
+
+        for _ in 2..AVERAGED_FRAMES {
+            timer.record(Duration::from_millis(10));
+        }
+        assert_eq!(timer.average(), Some(Duration::from_millis(10)));
+        assert!((fps(&timer) - 100.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,197 @@
+use gfx_hal::format::Format;
+use gfx_hal::pso::{AttributeDesc, Element, VertexBufferDesc, VertexInputRate};
+
+/// A single attribute of a vertex struct.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VertexAttribute {
+    pub format: Format,
+    /// Offset of the field within the vertex struct.
+    pub offset: u32,
+    /// Size of the Rust field backing the attribute, which has to match the size of `format`.
+    pub size: usize,
+}
+
+/// Implemented by `#[repr(C)]` structs stored in vertex buffers, usually through
+/// [`impl_vertex_format!`](crate::impl_vertex_format).
+pub trait VertexFormat: Copy {
+    /// The struct's attributes, in shader location order.
+    fn attributes() -> Vec<VertexAttribute>;
+}
+
+/// Implements [`VertexFormat`] for a `#[repr(C)]` struct, taking the offsets from the struct
+/// itself so they can't drift out of sync with the attribute formats:
+///
+/// ```ignore
+/// impl_vertex_format!(MeshVertex {
+///     position: [f32; 3] => Rgb32Sfloat,
+///     normal: [i8; 4] => Rgba8Snorm,
+///     uv: [u16; 2] => Rg16Unorm,
+/// });
+/// ```
+///
+/// Attributes are assigned consecutive shader locations in the order they are listed.
+#[macro_export]
+macro_rules! impl_vertex_format {
+    ($vertex:ty { $($field:ident: $ty:ty => $format:ident),* $(,)? }) => {
+        impl $crate::vertex::VertexFormat for $vertex {
+            fn attributes() -> Vec<$crate::vertex::VertexAttribute> {
+                vec![$($crate::vertex::VertexAttribute {
+                    format: gfx_hal::format::Format::$format,
+                    offset: std::mem::offset_of!($vertex, $field) as u32,
+                    size: std::mem::size_of::<$ty>(),
+                }),*]
+            }
+        }
+    };
+}
+
+/// Vertex buffer bindings and attributes consumed by a pipeline.
+#[derive(Debug, Clone, Default)]
+pub struct VertexLayout {
+    pub buffers: Vec<VertexBufferDesc>,
+    pub attributes: Vec<AttributeDesc>,
+}
+
+impl VertexLayout {
+    /// Layout without any vertex buffers, for shaders generating their vertices from
+    /// `gl_VertexIndex`.
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    /// Adds a vertex buffer of `V`s at the next binding, whose attributes take the next free
+    /// shader locations.
+    ///
+    /// Panics if an attribute's format doesn't match the size of its field, or if it doesn't fit
+    /// within the vertex.
+    pub fn with_buffer<V: VertexFormat>(mut self, rate: VertexInputRate) -> Self {
+        let binding = self.buffers.len() as u32;
+        let stride = std::mem::size_of::<V>() as u32;
+
+        for attribute in V::attributes() {
+            let format_size = attribute.format.surface_desc().bits as usize / 8;
+
+            assert_eq!(
+                format_size,
+                attribute.size,
+                "{:?} doesn't match the size of the field at offset {} of {}",
+                attribute.format,
+                attribute.offset,
+                std::any::type_name::<V>(),
+            );
+            assert!(
+                attribute.offset + format_size as u32 <= stride,
+                "{:?} at offset {} exceeds the size of {}",
+                attribute.format,
+                attribute.offset,
+                std::any::type_name::<V>(),
+            );
+
+            self.attributes.push(AttributeDesc {
+                location: self.attributes.len() as u32,
+                binding,
+                element: Element {
+                    format: attribute.format,
+                    offset: attribute.offset,
+                },
+            });
+        }
+
+        self.buffers.push(VertexBufferDesc {
+            binding,
+            stride,
+            rate,
+        });
+
+        self
+    }
+}
+
+/// Vertex of the built-in triangle.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Vertex {
+    pub position: [f32; 2],
+    pub color: [f32; 3],
+}
+
+crate::impl_vertex_format!(Vertex {
+    position: [f32; 2] => Rg32Sfloat,
+    color: [f32; 3] => Rgb32Sfloat,
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy)]
+    #[repr(C)]
+    struct CompactVertex {
+        position: [f32; 3],
+        normal: [i8; 4],
+        uv: [u16; 2],
+    }
+
+    crate::impl_vertex_format!(CompactVertex {
+        position: [f32; 3] => Rgb32Sfloat,
+        normal: [i8; 4] => Rgba8Snorm,
+        uv: [u16; 2] => Rg16Unorm,
+    });
+
+    #[derive(Debug, Clone, Copy)]
+    #[repr(C)]
+    struct MismatchedVertex {
+        position: [f32; 2],
+    }
+
+    crate::impl_vertex_format!(MismatchedVertex {
+        position: [f32; 2] => Rgb32Sfloat,
+    });
+
+    fn attribute(location: u32, binding: u32, format: Format, offset: u32) -> AttributeDesc {
+        AttributeDesc {
+            location,
+            binding,
+            element: Element { format, offset },
+        }
+    }
+
+    #[test]
+    fn compact_vertex_layout() {
+        let layout = VertexLayout::empty()
+            .with_buffer::<Vertex>(VertexInputRate::Vertex)
+            .with_buffer::<CompactVertex>(VertexInputRate::Instance(1));
+
+        assert_eq!(
+            layout.buffers,
+            [
+                VertexBufferDesc {
+                    binding: 0,
+                    stride: 20,
+                    rate: VertexInputRate::Vertex,
+                },
+                VertexBufferDesc {
+                    binding: 1,
+                    stride: 20,
+                    rate: VertexInputRate::Instance(1),
+                },
+            ]
+        );
+        assert_eq!(
+            layout.attributes,
+            [
+                attribute(0, 0, Format::Rg32Sfloat, 0),
+                attribute(1, 0, Format::Rgb32Sfloat, 8),
+                attribute(2, 1, Format::Rgb32Sfloat, 0),
+                attribute(3, 1, Format::Rgba8Snorm, 12),
+                attribute(4, 1, Format::Rg16Unorm, 16),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't match the size of the field")]
+    fn mismatched_format_is_rejected() {
+        VertexLayout::empty().with_buffer::<MismatchedVertex>(VertexInputRate::Vertex);
+    }
+}